@@ -1,13 +1,16 @@
 use std::time::Duration;
 
 use axum::{
+    handler::HandlerWithoutStateExt,
+    middleware::from_fn_with_state,
     routing::{delete, get, post},
     Router,
 };
 use tower::ServiceBuilder;
 use tower_http::{
+    catch_panic::CatchPanicLayer,
     compression::CompressionLayer,
-    services::{ServeDir, ServeFile},
+    services::ServeDir,
     timeout::TimeoutLayer,
     trace::TraceLayer,
     validate_request::ValidateRequestHeaderLayer,
@@ -15,40 +18,74 @@ use tower_http::{
 };
 
 use crate::{
+    error::{handle_panic, render_error_pages},
     handlers::{
-        delete_post::delete_post, get_about::get_about, get_post::get_post, get_root::get_root,
-        not_found::not_found, upload_post::upload_post,
+        delete_post::delete_post,
+        get_about::get_about,
+        get_feed::{get_atom, get_json_feed, get_rss, get_tag_atom},
+        get_post::get_post,
+        get_root::{get_page, get_root},
+        get_sitemap::{get_robots, get_sitemap},
+        get_tag::get_tag,
+        get_tags::get_tags,
+        not_found::not_found,
+        upload_post::upload_post,
     },
     state::AppState,
 };
 
 pub(crate) fn create_app(state: AppState) -> Router {
-    let serve_dir =
-        ServeDir::new("./public").not_found_service(ServeFile::new("./public/404.html"));
+    // Note: the public directory also holds the templates, a missing asset
+    // gets the rendered 404 page instead. The precompressed variants written
+    // by `bloggy build` are served when present.
+    let serve_dir = ServeDir::new(&state.config.paths.public)
+        .precompressed_br()
+        .precompressed_gzip()
+        .precompressed_zstd()
+        .not_found_service(not_found.into_service());
 
+    // Note: the rendered pages are precompressed, only the other responses
+    // are compressed on the fly. Deflate is left out as the pages have no
+    // such variant, and their entity tags only match the identity one.
     let middleware = ServiceBuilder::new()
         .layer(TimeoutLayer::new(Duration::from_secs(5)))
         .map_response_body(axum::body::boxed)
-        .layer(CompressionLayer::new())
+        .layer(CompressionLayer::new().no_deflate())
         .layer(TraceLayer::new_for_http());
 
     let middleware_copy = middleware.clone();
 
+    // Note: the errors of the pages, including the panics and the unknown
+    // routes, are answered with the rendered error pages, the ones of the API
+    // with a message.
     let render_routes = Router::new()
         .route("/", get(get_root))
         .route("/about", get(get_about))
+        .route("/atom.xml", get(get_atom))
+        .route("/feed.json", get(get_json_feed))
+        .route("/feed.xml", get(get_rss))
+        .route("/page/:page", get(get_page))
         .route("/posts/:id", get(get_post))
+        .route("/robots.txt", get(get_robots))
+        .route("/sitemap.xml", get(get_sitemap))
+        .route("/tags", get(get_tags))
+        .route("/tags/:tag", get(get_tag))
+        .route("/tags/:tag/atom.xml", get(get_tag_atom))
         .nest_service("/public", serve_dir)
+        .fallback(not_found)
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(from_fn_with_state(state.clone(), render_error_pages))
         .layer(middleware);
 
     let api_routes = Router::new()
         .route("/post", post(upload_post))
         .route("/posts/:id", delete(delete_post))
-        .layer(middleware_copy.layer(ValidateRequestHeaderLayer::bearer("TODO")));
+        .layer(middleware_copy.layer(ValidateRequestHeaderLayer::bearer(
+            &state.config.server.token,
+        )));
 
     Router::new()
         .merge(Router::new().nest("/api", api_routes))
         .merge(render_routes)
-        .fallback(not_found)
         .with_state(state)
 }
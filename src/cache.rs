@@ -0,0 +1,369 @@
+use std::{fmt::Write as _, io::Write};
+
+use anyhow::Result;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use brotli::enc::BrotliEncoderParams;
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    date::{parse_http_date, to_http_date},
+    state::Page,
+};
+
+/// Content type of the rendered pages.
+pub(crate) const HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+
+/// Quality of the smallest brotli variants, the best one.
+const BROTLI_BEST_QUALITY: i32 = 11;
+
+/// Quality of the brotli variants compressed on the fly, a tenth of the time
+/// of the best one.
+const BROTLI_FAST_QUALITY: i32 = 5;
+
+/// Level of the smallest zstd variants, the best one not needing more memory
+/// to decompress.
+const ZSTD_BEST_LEVEL: i32 = 19;
+
+/// Level of the zstd variants compressed on the fly, the default one.
+const ZSTD_FAST_LEVEL: i32 = 3;
+
+/// How much the variants are compressed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum CompressionLevel {
+    /// The smallest variants, e.g. for the pages of an export.
+    Best,
+    /// Variants quicker to compress, e.g. for the pages rendered again while
+    /// serving.
+    Fast,
+}
+
+/// Encodings of the precompressed variants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    /// Every encoding, from the most to the least preferred one.
+    pub(crate) const ALL: [Self; 3] = [Self::Brotli, Self::Zstd, Self::Gzip];
+
+    /// Name of the encoding, as found in the `Accept-Encoding` header.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Extension of the precompressed files, as `ServeDir` looks for them.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+}
+
+/// Compresses some contents with the given encoding.
+pub(crate) fn compress(
+    encoding: Encoding,
+    level: CompressionLevel,
+    contents: &[u8],
+) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut compressed = Vec::new();
+
+            brotli::BrotliCompress(
+                &mut &contents[..],
+                &mut compressed,
+                &BrotliEncoderParams {
+                    quality: match level {
+                        CompressionLevel::Best => BROTLI_BEST_QUALITY,
+                        CompressionLevel::Fast => BROTLI_FAST_QUALITY,
+                    },
+                    ..BrotliEncoderParams::default()
+                },
+            )?;
+
+            Ok(compressed)
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(
+                Vec::new(),
+                match level {
+                    CompressionLevel::Best => Compression::best(),
+                    CompressionLevel::Fast => Compression::default(),
+                },
+            );
+
+            encoder.write_all(contents)?;
+
+            Ok(encoder.finish()?)
+        }
+        Encoding::Zstd => Ok(zstd::bulk::compress(
+            contents,
+            match level {
+                CompressionLevel::Best => ZSTD_BEST_LEVEL,
+                CompressionLevel::Fast => ZSTD_FAST_LEVEL,
+            },
+        )?),
+    }
+}
+
+/// Gets the encoding of the variant the client prefers, if any.
+/// Note: the server's preference breaks the ties.
+pub(crate) fn get_preferred_encoding(request_headers: &HeaderMap) -> Option<Encoding> {
+    let accept_encoding = request_headers
+        .get(header::ACCEPT_ENCODING)?
+        .to_str()
+        .ok()?;
+    let qualities = accept_encoding
+        .split(',')
+        .filter_map(|coding| {
+            let mut parameters = coding.split(';');
+            let name = parameters.next()?.trim().to_ascii_lowercase();
+            let quality = match parameters.find_map(|parameter| parameter.trim().strip_prefix("q="))
+            {
+                Some(quality) => quality.trim().parse::<f32>().ok()?,
+                None => 1.0,
+            };
+
+            Some((name, quality))
+        })
+        .collect::<Vec<(String, f32)>>();
+    let get_quality = |name: &str| {
+        qualities
+            .iter()
+            .find(|(coding, _)| coding == name)
+            .map(|(_, quality)| *quality)
+    };
+    let wildcard_quality = get_quality("*");
+
+    Encoding::ALL
+        .into_iter()
+        .filter_map(|encoding| {
+            let quality = get_quality(encoding.name()).or(wildcard_quality)?;
+
+            (quality > 0.0).then_some((encoding, quality))
+        })
+        .fold(None, |preferred, (encoding, quality)| match preferred {
+            Some((_, preferred_quality)) if preferred_quality >= quality => preferred,
+            _ => Some((encoding, quality)),
+        })
+        .map(|(encoding, _)| encoding)
+}
+
+/// Hashes some contents with SHA-256, as lowercase hexadecimal.
+pub(crate) fn get_content_hash(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    let mut hash = String::with_capacity(digest.len() * 2);
+
+    // Note: writing to a string can't fail.
+    for byte in digest {
+        let _ = write!(hash, "{byte:02x}");
+    }
+
+    hash
+}
+
+/// Builds the response of a rendered page along with its validators.
+/// Returns `304 Not Modified` without a body if the client already has the
+/// page.
+pub(crate) fn get_page_response(
+    request_headers: &HeaderMap,
+    page: &Page,
+    content_type: &'static str,
+    cache_control: &str,
+) -> Response {
+    let encoding = get_preferred_encoding(request_headers);
+    // Note: each variant has its own entity tag, as they differ byte wise.
+    let etag = page.get_etag(encoding);
+    let mut headers = HeaderMap::new();
+
+    // Note: the values are checked when the configuration is loaded and when
+    // the page is rendered.
+    for (name, value) in [
+        (header::CACHE_CONTROL, Some(cache_control.to_owned())),
+        (header::ETAG, Some(etag.clone())),
+        (header::LAST_MODIFIED, page.last_modified.map(to_http_date)),
+    ] {
+        if let Some(value) = value.and_then(|value| HeaderValue::try_from(value).ok()) {
+            headers.insert(name, value);
+        }
+    }
+
+    if is_not_modified(request_headers, &etag, page) {
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    (
+        headers,
+        get_variant_response(StatusCode::OK, page, encoding, content_type),
+    )
+        .into_response()
+}
+
+/// Builds the response of a page with the variant of the given encoding,
+/// without any validator.
+pub(crate) fn get_variant_response(
+    status: StatusCode,
+    page: &Page,
+    encoding: Option<Encoding>,
+    content_type: &'static str,
+) -> Response {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+    if let Some(encoding) = encoding {
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.name()),
+        );
+    }
+
+    (status, headers, page.get_variant(encoding)).into_response()
+}
+
+/// Checks whether the client already has the page.
+/// Note: `If-Modified-Since` is ignored when `If-None-Match` is present, as
+/// the entity tag is more precise.
+fn is_not_modified(request_headers: &HeaderMap, etag: &str, page: &Page) -> bool {
+    if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+        // Note: the comparison is weak, i.e. the `W/` prefix doesn't matter.
+        return if_none_match.to_str().map_or(false, |if_none_match| {
+            if_none_match.trim() == "*"
+                || if_none_match
+                    .split(',')
+                    .any(|candidate| candidate.trim().trim_start_matches("W/") == etag)
+        });
+    }
+
+    // Note: HTTP dates are precise to the second.
+    request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|if_modified_since| if_modified_since.to_str().ok())
+        .and_then(parse_http_date)
+        .zip(page.last_modified)
+        .map_or(false, |(if_modified_since, last_modified)| {
+            last_modified.unix_timestamp() <= if_modified_since.unix_timestamp()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn headers(pairs: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    fn preferred_encoding(accept_encoding: &'static str) -> Option<Encoding> {
+        get_preferred_encoding(&headers(&[(header::ACCEPT_ENCODING, accept_encoding)]))
+    }
+
+    #[test]
+    fn prefers_encodings_by_quality() {
+        assert_eq!(get_preferred_encoding(&HeaderMap::new()), None);
+        assert_eq!(preferred_encoding("identity"), None);
+        assert_eq!(preferred_encoding("gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(preferred_encoding("GZIP, zstd"), Some(Encoding::Zstd));
+        assert_eq!(
+            preferred_encoding("br;q=0.5, gzip;q=0.8"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            preferred_encoding("zstd; q=0.5, br ;q=0.5"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(preferred_encoding("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(preferred_encoding("br;q=nope"), None);
+    }
+
+    #[test]
+    fn prefers_encodings_by_wildcard() {
+        assert_eq!(preferred_encoding("*"), Some(Encoding::Brotli));
+        assert_eq!(preferred_encoding("*, br;q=0"), Some(Encoding::Zstd));
+        assert_eq!(preferred_encoding("*;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(preferred_encoding("*;q=0"), None);
+        assert_eq!(preferred_encoding("*;q=0, gzip"), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn matches_entity_tags() {
+        let page = Page::new("body", None, CompressionLevel::Fast).unwrap();
+        let etag = page.get_etag(Some(Encoding::Gzip));
+        let is_matching = |if_none_match: String| {
+            let mut request_headers = HeaderMap::new();
+
+            request_headers.insert(
+                header::IF_NONE_MATCH,
+                HeaderValue::try_from(if_none_match).unwrap(),
+            );
+
+            is_not_modified(&request_headers, &etag, &page)
+        };
+
+        assert!(is_matching(etag.clone()));
+        assert!(is_matching(format!("W/{etag}")));
+        assert!(is_matching(format!("\"other\", W/{etag}")));
+        assert!(is_matching("*".to_owned()));
+        assert!(!is_matching("\"other\"".to_owned()));
+        assert!(!is_matching(page.get_etag(None)));
+        assert!(!is_not_modified(&HeaderMap::new(), &etag, &page));
+    }
+
+    #[test]
+    fn compares_modification_dates() {
+        let page = Page::new(
+            "body",
+            Some(datetime!(2023-05-01 10:20:30.5 UTC)),
+            CompressionLevel::Fast,
+        )
+        .unwrap();
+        let etag = page.get_etag(None);
+
+        for (request_headers, expected) in [
+            (
+                headers(&[(header::IF_MODIFIED_SINCE, "Mon, 01 May 2023 10:20:30 GMT")]),
+                true,
+            ),
+            (
+                headers(&[(header::IF_MODIFIED_SINCE, "Mon, 01 May 2023 10:20:29 GMT")]),
+                false,
+            ),
+            (headers(&[(header::IF_MODIFIED_SINCE, "yesterday")]), false),
+            // The entity tag takes precedence.
+            (
+                headers(&[
+                    (header::IF_MODIFIED_SINCE, "Mon, 01 May 2023 10:20:30 GMT"),
+                    (header::IF_NONE_MATCH, "\"other\""),
+                ]),
+                false,
+            ),
+        ] {
+            assert_eq!(
+                is_not_modified(&request_headers, &etag, &page),
+                expected,
+                "{request_headers:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::DEFAULT_CONFIG_PATH;
+
+/// A tiny blog engine serving markdown posts.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub(crate) struct Cli {
+    /// Path of the configuration file.
+    #[arg(long, global = true, env = "BLOGGY_CONFIG", default_value = DEFAULT_CONFIG_PATH)]
+    pub(crate) config: PathBuf,
+    /// Directory of the posts, overriding the configuration.
+    #[arg(long, global = true)]
+    pub(crate) posts_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    pub(crate) command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Exports the site statically.
+    Build {
+        /// Output directory, overriding the configuration.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Validates all posts without serving them.
+    Check,
+    /// Scaffolds a new post.
+    New {
+        /// Title of the post, also used to name the file.
+        title: String,
+        /// Tags of the post.
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+    },
+    /// Runs the server.
+    Serve {
+        /// Port to listen on, overriding the configuration.
+        #[arg(long)]
+        port: Option<u16>,
+    },
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::{
+    fs::{copy, create_dir_all, read, read_dir, write},
+    sync::Mutex,
+};
+
+use crate::{
+    cache::{CompressionLevel, Encoding},
+    config::Config,
+    state::{Page, Post},
+    templates::{generate_initial_templates, get_page_url, templates_manager, InitialTemplates},
+};
+
+/// Extensions of the assets precompressed along with the pages.
+/// Note: most other formats, e.g. images or fonts, are already compressed.
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "css", "html", "js", "json", "map", "mjs", "svg", "txt", "xml",
+];
+
+/// Exports the site to the output directory, one file per route of the
+/// server, so that it can be published on any static hosting.
+/// Pages are written as the `index.html` file of their route and the 404
+/// page as `404.html`, each along with its precompressed variants.
+/// Note: files left from a previous export are overwritten but never
+/// removed.
+pub(crate) async fn build(config: Arc<Config>) -> Result<()> {
+    let posts = Arc::new(Mutex::new(HashMap::<String, Post>::new()));
+
+    let (sender, ..) = templates_manager(Arc::clone(&config), Arc::clone(&posts)).await?;
+
+    let InitialTemplates {
+        about_template,
+        derived_pages,
+        error_pages,
+        ..
+    } = generate_initial_templates(&config, Arc::clone(&posts), sender, None).await?;
+
+    let output = &config.paths.output;
+
+    create_dir_all(output).await?;
+
+    for (index, root_page) in derived_pages.root_pages.iter().enumerate() {
+        write_page(output, &get_page_url(index + 1), root_page).await?;
+    }
+
+    for (original_name, post) in posts.lock().await.iter() {
+        write_page(
+            output,
+            &format!("/posts/{original_name}"),
+            &post.rendered_template,
+        )
+        .await?;
+    }
+
+    if !about_template.body.is_empty() {
+        write_page(output, "/about", &about_template).await?;
+    }
+
+    write_page(output, "/tags", &derived_pages.tag_index).await?;
+
+    for (tag, tag_page) in &derived_pages.tags {
+        write_page(output, &format!("/tags/{tag}"), tag_page).await?;
+    }
+
+    for (tag, tag_atom) in &derived_pages.tag_atoms {
+        write_file(output, &format!("/tags/{tag}/atom.xml"), tag_atom).await?;
+    }
+
+    for (route, page) in [
+        ("/404.html", &error_pages.not_found),
+        ("/atom.xml", &derived_pages.atom),
+        ("/feed.json", &derived_pages.json_feed),
+        ("/feed.xml", &derived_pages.rss),
+        ("/robots.txt", &derived_pages.robots),
+        ("/sitemap.xml", &derived_pages.sitemap),
+    ] {
+        write_file(output, route, page).await?;
+    }
+
+    copy_dir(&config.paths.public, &output.join("public")).await?;
+
+    tracing::info!("site exported to {}", output.display());
+
+    Ok(())
+}
+
+/// Writes a page as the `index.html` file of its route.
+async fn write_page(output: &Path, route: &str, page: &Page) -> Result<()> {
+    write_file(
+        output,
+        &format!("{}/index.html", route.trim_end_matches('/')),
+        page,
+    )
+    .await
+}
+
+/// Writes the file served at the given route, along with its precompressed
+/// variants.
+async fn write_file(output: &Path, route: &str, page: &Page) -> Result<()> {
+    let relative_path = Path::new(route.trim_start_matches('/'));
+
+    // Note: tags come from the posts and could escape the output directory.
+    if !relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("invalid route: {route:?}");
+    }
+
+    let path = output.join(relative_path);
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).await?;
+    }
+
+    write(&path, &page.body)
+        .await
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    write_variants(&path, page).await
+}
+
+/// Writes the precompressed variants of a file next to it, e.g. `index.html.gz`
+/// for `index.html`, as static hostings and `ServeDir` look for them.
+async fn write_variants(path: &Path, page: &Page) -> Result<()> {
+    for encoding in Encoding::ALL {
+        let mut variant_path = path.as_os_str().to_owned();
+
+        variant_path.push(".");
+        variant_path.push(encoding.extension());
+
+        write(&variant_path, page.get_variant(Some(encoding)))
+            .await
+            .with_context(|| format!("failed to write {}", Path::new(&variant_path).display()))?;
+    }
+
+    Ok(())
+}
+
+/// Checks whether an asset is worth precompressing, i.e. is text.
+fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .map_or(false, |extension| {
+            COMPRESSIBLE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+/// Copies a directory recursively, precompressing the text assets.
+async fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+
+    while let Some((from, to)) = pending.pop() {
+        create_dir_all(&to).await?;
+
+        let mut entries = read_dir(&from)
+            .await
+            .with_context(|| format!("failed to read {}", from.display()))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let target = to.join(entry.file_name());
+
+            // Note: symbolic links are followed.
+            if entry.path().is_dir() {
+                pending.push((entry.path(), target));
+            } else if is_compressible(&entry.path()) {
+                let page =
+                    Page::create(read(entry.path()).await?, None, CompressionLevel::Best).await?;
+
+                write(&target, &page.body).await?;
+                write_variants(&target, &page).await?;
+            } else {
+                copy(entry.path(), &target).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Result};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::Config,
+    state::Post,
+    templates::{generate_initial_templates, templates_manager, InitialTemplates},
+};
+
+/// Loads and renders every post, reporting the invalid ones.
+pub(crate) async fn check(config: Arc<Config>) -> Result<()> {
+    let posts = Arc::new(Mutex::new(HashMap::<String, Post>::new()));
+
+    let (sender, ..) = templates_manager(Arc::clone(&config), Arc::clone(&posts)).await?;
+
+    let InitialTemplates { failed_posts, .. } =
+        generate_initial_templates(&config, Arc::clone(&posts), sender, None).await?;
+
+    let valid_count = posts.lock().await.len();
+
+    for (name, error) in &failed_posts {
+        eprintln!("{name}: {error:#}");
+    }
+
+    if !failed_posts.is_empty() {
+        bail!(
+            "{} invalid post(s), {valid_count} valid post(s)",
+            failed_posts.len()
+        );
+    }
+
+    println!("{valid_count} valid post(s)");
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::{
+    fs::{create_dir_all, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::{config::Config, date::to_rfc3339, file::slugify};
+
+#[derive(Debug, Serialize)]
+struct NewFrontMatter<'a> {
+    title: &'a str,
+    date: String,
+    tags: &'a [String],
+}
+
+/// Scaffolds a post named after its title.
+/// Returns the path of the new post.
+pub(crate) async fn new_post(config: &Config, title: &str, tags: &[String]) -> Result<PathBuf> {
+    let slug = slugify(title);
+    let now = OffsetDateTime::now_utc();
+
+    if slug.is_empty() {
+        bail!("the title {title:?} can't be turned into a file name");
+    }
+
+    create_dir_all(&config.paths.posts).await?;
+
+    let path = config.paths.posts.join(format!("{slug}.md"));
+    let front_matter = serde_yaml::to_string(&NewFrontMatter {
+        title,
+        date: to_rfc3339(now.replace_nanosecond(0).unwrap_or(now)),
+        tags,
+    })?;
+
+    // Note: an existing post is never overwritten.
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await
+        .with_context(|| format!("failed to create {}", path.display()))?;
+
+    file.write_all(format!("---\n{front_matter}---\n\n").as_bytes())
+        .await?;
+
+    Ok(path)
+}
@@ -0,0 +1,74 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::sync::Mutex;
+
+use crate::{
+    app::create_app,
+    config::Config,
+    state::{AppState, Post, Site},
+    templates::{generate_initial_templates, templates_manager, InitialTemplates},
+    watcher::{async_watch, async_watch_templates},
+};
+
+/// Runs the server and watches the posts.
+pub(crate) async fn serve(config: Arc<Config>) -> Result<()> {
+    config.validate_server()?;
+
+    // Add certificate and private key.
+    let tls_config =
+        RustlsConfig::from_pem_file(&config.server.certificate, &config.server.key).await?;
+
+    let posts = Arc::new(Mutex::new(HashMap::<String, Post>::new()));
+
+    let (sender, renderer, renderer_status) =
+        templates_manager(Arc::clone(&config), Arc::clone(&posts)).await?;
+
+    // Get all templates.
+    let InitialTemplates {
+        about_template,
+        derived_pages,
+        error_pages,
+        ..
+    } = generate_initial_templates(&config, Arc::clone(&posts), sender.clone(), None).await?;
+
+    let site = Arc::new(ArcSwap::from_pointee(Site::new(
+        about_template,
+        derived_pages,
+        error_pages,
+        &*posts.lock().await,
+    )));
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
+
+    let state = AppState::new(config, posts, renderer_status, site);
+
+    let state_clone = state.clone();
+    let sender_clone = sender.clone();
+
+    // Spawn the watcher task.
+    tokio::spawn(async move {
+        async_watch(state_clone, sender_clone).await?;
+
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let state_clone = state.clone();
+
+    // Spawn the templates watcher task.
+    tokio::spawn(async move {
+        async_watch_templates(state_clone, sender, renderer).await?;
+
+        Ok::<_, anyhow::Error>(())
+    });
+
+    tracing::debug!("listening on {}", addr);
+
+    axum_server::bind_rustls(addr, tls_config)
+        .serve(create_app(state).into_make_service())
+        .await?;
+
+    Ok(())
+}
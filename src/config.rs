@@ -0,0 +1,420 @@
+use std::{
+    env,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::http::HeaderValue;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::date::DateSources;
+
+/// Default path of the configuration file.
+pub(crate) const DEFAULT_CONFIG_PATH: &str = "./bloggy.toml";
+
+/// Prefix of the environment variables overriding the configuration.
+const ENV_PREFIX: &str = "BLOGGY_";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SortDirection {
+    Ascending,
+    /// Newest posts first.
+    #[default]
+    Descending,
+}
+
+impl FromStr for SortDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "ascending" | "asc" => Ok(Self::Ascending),
+            "descending" | "desc" => Ok(Self::Descending),
+            _ => Err(anyhow!("expected \"ascending\" or \"descending\"")),
+        }
+    }
+}
+
+/// How the posts directory is watched.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum WatchMode {
+    /// Polls on network and virtual filesystems, uses native events otherwise.
+    #[default]
+    Auto,
+    /// Native events, e.g. inotify on Linux.
+    Native,
+    /// Periodic scans of the directory.
+    Poll,
+}
+
+impl FromStr for WatchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "native" => Ok(Self::Native),
+            "poll" => Ok(Self::Poll),
+            _ => Err(anyhow!("expected \"auto\", \"native\" or \"poll\"")),
+        }
+    }
+}
+
+/// `Cache-Control` header of each type of route.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CacheConfig {
+    /// The feeds, the sitemap and `robots.txt`.
+    pub(crate) feeds: String,
+    /// The root listing and the tag pages.
+    pub(crate) pages: String,
+    /// The posts and the about page.
+    pub(crate) posts: String,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            feeds: "public, max-age=3600".to_owned(),
+            pages: "public, no-cache".to_owned(),
+            posts: "public, no-cache".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ListingConfig {
+    /// Number of posts per page.
+    pub(crate) page_size: usize,
+    pub(crate) sort: SortDirection,
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 10,
+            sort: SortDirection::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DatesConfig {
+    /// Where to look for the publication date of a post, in order.
+    pub(crate) sources: DateSources,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FeedConfig {
+    /// Whether to put the whole post in the feeds instead of the excerpt.
+    pub(crate) full_content: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PathsConfig {
+    /// Where `bloggy build` exports the static site.
+    pub(crate) output: PathBuf,
+    pub(crate) posts: PathBuf,
+    /// Static assets, served under `/public`.
+    pub(crate) public: PathBuf,
+    /// Templates, falling back to the built-in ones.
+    pub(crate) templates: PathBuf,
+    /// Syntax highlighting themes.
+    pub(crate) themes: PathBuf,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("./dist"),
+            posts: PathBuf::from("./posts"),
+            public: PathBuf::from("./public"),
+            templates: PathBuf::from("./public"),
+            themes: PathBuf::from("./themes"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RendererConfig {
+    /// Number of renders waiting for a worker before the requesters have to
+    /// wait too.
+    pub(crate) queue_size: usize,
+    /// Number of renders running in parallel, `0` meaning one per CPU.
+    pub(crate) workers: usize,
+}
+
+impl RendererConfig {
+    /// Gets the number of workers, resolving `0` to the number of CPUs.
+    pub(crate) fn get_workers(&self) -> usize {
+        if self.workers > 0 {
+            return self.workers;
+        }
+
+        std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            queue_size: 64,
+            workers: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RobotsConfig {
+    /// Paths crawlers should not visit.
+    pub(crate) disallow: Vec<String>,
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        Self {
+            disallow: vec!["/api/".to_owned()],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ServerConfig {
+    /// PEM encoded TLS certificate.
+    pub(crate) certificate: PathBuf,
+    /// PEM encoded private key of the certificate.
+    pub(crate) key: PathBuf,
+    pub(crate) port: u16,
+    /// Bearer token protecting the API.
+    /// Note: it is never exposed to the templates.
+    #[serde(skip_serializing)]
+    pub(crate) token: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            certificate: PathBuf::from("./cert/certificate.pem"),
+            key: PathBuf::from("./cert/key.pem"),
+            port: 3443,
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SiteConfig {
+    /// Default author of the posts.
+    pub(crate) author: String,
+    /// Public URL of the blog, used for absolute links.
+    pub(crate) base_url: String,
+    pub(crate) description: String,
+    pub(crate) title: String,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            author: String::new(),
+            base_url: "https://localhost:3443".to_owned(),
+            description: String::new(),
+            title: "Bloggy".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WatcherConfig {
+    /// How long a post must stay untouched before being published again, in
+    /// milliseconds.
+    pub(crate) debounce_ms: u64,
+    pub(crate) mode: WatchMode,
+    /// Interval between two scans when polling, in milliseconds.
+    pub(crate) poll_interval_ms: u64,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 250,
+            mode: WatchMode::default(),
+            poll_interval_ms: 2000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub(crate) cache: CacheConfig,
+    pub(crate) dates: DatesConfig,
+    pub(crate) feed: FeedConfig,
+    pub(crate) listing: ListingConfig,
+    pub(crate) paths: PathsConfig,
+    pub(crate) renderer: RendererConfig,
+    pub(crate) robots: RobotsConfig,
+    pub(crate) server: ServerConfig,
+    pub(crate) site: SiteConfig,
+    pub(crate) watcher: WatcherConfig,
+}
+
+impl Config {
+    /// Loads the configuration file, if any, then applies the environment
+    /// overrides and validates the result.
+    /// Note: the server settings are validated separately, see
+    /// [`Config::validate_server`].
+    /// Every key can be set with a `BLOGGY_<SECTION>_<KEY>` variable, e.g.
+    /// `BLOGGY_SERVER_PORT`.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let mut config = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+
+            toml::from_str(&contents)
+                .with_context(|| format!("invalid configuration in {}", path.display()))?
+        } else {
+            tracing::info!(
+                "{} not found, using the default configuration",
+                path.display()
+            );
+
+            Self::default()
+        };
+
+        config.override_from_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn override_from_env(&mut self) -> Result<()> {
+        override_from_env(&mut self.cache.feeds, "CACHE_FEEDS")?;
+        override_from_env(&mut self.cache.pages, "CACHE_PAGES")?;
+        override_from_env(&mut self.cache.posts, "CACHE_POSTS")?;
+        override_from_env(&mut self.dates.sources, "DATES_SOURCES")?;
+        override_from_env(&mut self.feed.full_content, "FEED_FULL_CONTENT")?;
+        override_from_env(&mut self.listing.page_size, "LISTING_PAGE_SIZE")?;
+        override_from_env(&mut self.listing.sort, "LISTING_SORT")?;
+        override_from_env(&mut self.paths.output, "PATHS_OUTPUT")?;
+        override_from_env(&mut self.paths.posts, "PATHS_POSTS")?;
+        override_from_env(&mut self.paths.public, "PATHS_PUBLIC")?;
+        override_from_env(&mut self.paths.templates, "PATHS_TEMPLATES")?;
+        override_from_env(&mut self.paths.themes, "PATHS_THEMES")?;
+        override_from_env(&mut self.renderer.queue_size, "RENDERER_QUEUE_SIZE")?;
+        override_from_env(&mut self.renderer.workers, "RENDERER_WORKERS")?;
+        override_from_env(&mut self.server.certificate, "SERVER_CERTIFICATE")?;
+        override_from_env(&mut self.server.key, "SERVER_KEY")?;
+        override_from_env(&mut self.server.port, "SERVER_PORT")?;
+        override_from_env(&mut self.server.token, "SERVER_TOKEN")?;
+        override_from_env(&mut self.site.author, "SITE_AUTHOR")?;
+        override_from_env(&mut self.site.base_url, "SITE_BASE_URL")?;
+        override_from_env(&mut self.site.description, "SITE_DESCRIPTION")?;
+        override_from_env(&mut self.site.title, "SITE_TITLE")?;
+        override_from_env(&mut self.watcher.debounce_ms, "WATCHER_DEBOUNCE_MS")?;
+        override_from_env(&mut self.watcher.mode, "WATCHER_MODE")?;
+        override_from_env(
+            &mut self.watcher.poll_interval_ms,
+            "WATCHER_POLL_INTERVAL_MS",
+        )?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.listing.page_size == 0 {
+            bail!("listing.page_size must be greater than 0");
+        }
+
+        let base_url = Url::parse(&self.site.base_url).with_context(|| {
+            format!("site.base_url is not a valid URL: {:?}", self.site.base_url)
+        })?;
+
+        if !matches!(base_url.scheme(), "http" | "https") {
+            bail!(
+                "site.base_url must be an HTTP(S) URL: {:?}",
+                self.site.base_url
+            );
+        }
+
+        for (name, cache_control) in [
+            ("cache.feeds", &self.cache.feeds),
+            ("cache.pages", &self.cache.pages),
+            ("cache.posts", &self.cache.posts),
+        ] {
+            if HeaderValue::from_str(cache_control).is_err() {
+                bail!("{name} is not a valid header value: {cache_control:?}");
+            }
+        }
+
+        if self.renderer.queue_size == 0 {
+            bail!("renderer.queue_size must be greater than 0");
+        }
+
+        if self.watcher.poll_interval_ms == 0 {
+            bail!("watcher.poll_interval_ms must be greater than 0");
+        }
+
+        if self.dates.sources.0.is_empty() {
+            bail!("dates.sources must not be empty");
+        }
+
+        if !self.paths.themes.is_dir() {
+            bail!(
+                "paths.themes does not exist: {}",
+                self.paths.themes.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Validates the settings only needed to run the server.
+    pub(crate) fn validate_server(&self) -> Result<()> {
+        if self.server.token.trim().is_empty() {
+            bail!("server.token must be set, e.g. with {ENV_PREFIX}SERVER_TOKEN");
+        }
+
+        for (name, path) in [
+            ("server.certificate", &self.server.certificate),
+            ("server.key", &self.server.key),
+        ] {
+            if !path.is_file() {
+                bail!("{name} does not exist: {}", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Overrides the value with the matching environment variable, if any.
+fn override_from_env<T>(value: &mut T, key: &str) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    let name = format!("{ENV_PREFIX}{key}");
+
+    if let Ok(raw) = env::var(&name) {
+        *value = raw
+            .parse()
+            .map_err(Into::into)
+            .with_context(|| format!("invalid value for {name}: {raw:?}"))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use time::{
+    format_description::{
+        well_known::{Rfc2822, Rfc3339},
+        FormatItem,
+    },
+    macros::format_description,
+    Date, OffsetDateTime, PrimitiveDateTime, UtcOffset,
+};
+
+const DATE_TIME_FORMATS: [&[FormatItem<'_>]; 2] = [
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+];
+
+const DATE_FORMAT: &[FormatItem<'_>] = format_description!("[year]-[month]-[day]");
+
+/// Format of the dates in the HTTP headers, always in GMT.
+const HTTP_DATE_FORMAT: &[FormatItem<'_>] = format_description!(
+    "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+);
+
+/// Where the publication date of a post comes from.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DateSource {
+    /// The `date` key of the front matter.
+    FrontMatter,
+    /// A `YYYY-MM-DD` prefix in the file name.
+    FileName,
+    /// The modification time of the file.
+    Filesystem,
+}
+
+impl FromStr for DateSource {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim() {
+            "front_matter" => Ok(Self::FrontMatter),
+            "file_name" => Ok(Self::FileName),
+            "filesystem" => Ok(Self::Filesystem),
+            _ => Err(anyhow!(
+                "expected \"front_matter\", \"file_name\" or \"filesystem\""
+            )),
+        }
+    }
+}
+
+/// Ordered chain of date sources, the first one yielding a date wins.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub(crate) struct DateSources(pub(crate) Vec<DateSource>);
+
+impl Default for DateSources {
+    fn default() -> Self {
+        Self(vec![
+            DateSource::FrontMatter,
+            DateSource::FileName,
+            DateSource::Filesystem,
+        ])
+    }
+}
+
+impl FromStr for DateSources {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list, e.g. `front_matter,filesystem`.
+    fn from_str(value: &str) -> Result<Self> {
+        value
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+/// Candidate dates of a post, one per source.
+#[derive(Debug, Default)]
+pub(crate) struct DateCandidates {
+    pub(crate) file_name: Option<OffsetDateTime>,
+    pub(crate) filesystem: Option<OffsetDateTime>,
+    pub(crate) front_matter: Option<OffsetDateTime>,
+}
+
+impl DateCandidates {
+    /// Resolves the date by walking the sources in order.
+    pub(crate) fn resolve(&self, sources: &DateSources) -> Option<(OffsetDateTime, DateSource)> {
+        sources.0.iter().find_map(|source| {
+            match source {
+                DateSource::FrontMatter => self.front_matter,
+                DateSource::FileName => self.file_name,
+                DateSource::Filesystem => self.filesystem,
+            }
+            .map(|date| (date, *source))
+        })
+    }
+}
+
+/// Parses a date as found in posts.
+/// Accepts RFC 3339 timestamps, local date times and plain dates, the last
+/// two being interpreted as UTC.
+pub(crate) fn parse_date(input: &str) -> Option<OffsetDateTime> {
+    let input = input.trim();
+
+    if let Ok(offset_date_time) = OffsetDateTime::parse(input, &Rfc3339) {
+        return Some(offset_date_time);
+    }
+
+    DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| PrimitiveDateTime::parse(input, format).ok())
+        .or_else(|| Date::parse(input, DATE_FORMAT).ok().map(Date::midnight))
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+/// Splits a `YYYY-MM-DD` prefix from a file name, e.g. `2023-05-01-title`.
+/// Returns the date and the rest of the name.
+pub(crate) fn parse_date_prefix(name: &str) -> Option<(OffsetDateTime, &str)> {
+    let prefix = name.get(..10)?;
+    let rest = &name[10..];
+
+    // The date must be followed by a separator or nothing at all.
+    let rest = match rest.chars().next() {
+        None => rest,
+        Some('-' | '_' | ' ') => &rest[1..],
+        Some(_) => return None,
+    };
+
+    Date::parse(prefix, DATE_FORMAT)
+        .ok()
+        .map(|date| (date.midnight().assume_utc(), rest))
+}
+
+/// Formats a date for humans, e.g. `Mon, 01 May 2023 00:00:00 +0000`.
+pub(crate) fn to_rfc2822(date: OffsetDateTime) -> String {
+    date.format(&Rfc2822).unwrap_or_default()
+}
+
+/// Formats a date for machines, e.g. `2023-05-01T00:00:00Z`.
+pub(crate) fn to_rfc3339(date: OffsetDateTime) -> String {
+    date.format(&Rfc3339).unwrap_or_default()
+}
+
+/// Formats a date for the HTTP headers, e.g. `Mon, 01 May 2023 00:00:00 GMT`.
+pub(crate) fn to_http_date(date: OffsetDateTime) -> String {
+    date.to_offset(UtcOffset::UTC)
+        .format(HTTP_DATE_FORMAT)
+        .unwrap_or_default()
+}
+
+/// Parses a date from the HTTP headers.
+/// Note: the obsolete formats are not supported.
+pub(crate) fn parse_http_date(input: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(input.trim(), HTTP_DATE_FORMAT)
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+/// Serde helpers for optional dates, serialized as RFC 3339.
+pub(crate) mod option {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+    use super::parse_date;
+
+    // Note: serde imposes this signature.
+    #[allow(clippy::ref_option)]
+    pub(crate) fn serialize<S>(
+        date: &Option<OffsetDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => serializer
+                .serialize_some(&date.format(&Rfc3339).map_err(serde::ser::Error::custom)?),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|date| {
+                parse_date(&date).ok_or_else(|| D::Error::custom(format!("invalid date: {date:?}")))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(
+            parse_date("2023-05-01T10:20:30+02:00"),
+            Some(datetime!(2023-05-01 10:20:30 +2))
+        );
+        assert_eq!(
+            parse_date(" 2023-05-01 10:20:30 "),
+            Some(datetime!(2023-05-01 10:20:30 UTC))
+        );
+        assert_eq!(
+            parse_date("2023-05-01T10:20:30"),
+            Some(datetime!(2023-05-01 10:20:30 UTC))
+        );
+        assert_eq!(
+            parse_date("2023-05-01"),
+            Some(datetime!(2023-05-01 0:00 UTC))
+        );
+        assert_eq!(parse_date("2023-13-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn splits_date_prefixes() {
+        let date = datetime!(2023-05-01 0:00 UTC);
+
+        assert_eq!(parse_date_prefix("2023-05-01-title"), Some((date, "title")));
+        assert_eq!(parse_date_prefix("2023-05-01_title"), Some((date, "title")));
+        assert_eq!(parse_date_prefix("2023-05-01 title"), Some((date, "title")));
+        assert_eq!(parse_date_prefix("2023-05-01"), Some((date, "")));
+        assert_eq!(parse_date_prefix("2023-05-01x"), None);
+        assert_eq!(parse_date_prefix("2023-13-01-x"), None);
+        assert_eq!(parse_date_prefix("title"), None);
+    }
+
+    #[test]
+    fn splits_date_prefixes_of_multibyte_names() {
+        assert_eq!(parse_date_prefix("été"), None);
+        assert_eq!(parse_date_prefix("ééééé"), None);
+        // The tenth byte is in the middle of a character.
+        assert_eq!(parse_date_prefix("2023-05-0é"), None);
+        assert_eq!(
+            parse_date_prefix("2023-05-01-été"),
+            Some((datetime!(2023-05-01 0:00 UTC), "été"))
+        );
+    }
+
+    #[test]
+    fn resolves_dates_in_source_order() {
+        let candidates = DateCandidates {
+            file_name: Some(datetime!(2023-05-01 0:00 UTC)),
+            filesystem: Some(datetime!(2023-05-02 0:00 UTC)),
+            front_matter: None,
+        };
+
+        assert_eq!(
+            candidates.resolve(&DateSources::default()),
+            Some((datetime!(2023-05-01 0:00 UTC), DateSource::FileName))
+        );
+        assert_eq!(
+            candidates.resolve(&"filesystem, file_name".parse().unwrap()),
+            Some((datetime!(2023-05-02 0:00 UTC), DateSource::Filesystem))
+        );
+        assert_eq!(candidates.resolve(&"front_matter".parse().unwrap()), None);
+        assert_eq!(
+            DateCandidates {
+                front_matter: Some(datetime!(2023-04-30 0:00 UTC)),
+                ..candidates
+            }
+            .resolve(&DateSources::default()),
+            Some((datetime!(2023-04-30 0:00 UTC), DateSource::FrontMatter))
+        );
+        assert!("front_matter,nope".parse::<DateSources>().is_err());
+    }
+}
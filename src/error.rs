@@ -0,0 +1,133 @@
+use std::{
+    any::Any,
+    fmt::{self, Display, Formatter},
+};
+
+use anyhow::anyhow;
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    cache::{get_preferred_encoding, get_variant_response, HTML_CONTENT_TYPE},
+    state::AppState,
+    templates::RENDERER_RESTART_DELAY,
+};
+
+/// Errors of the handlers, each mapped to an HTTP status.
+/// Note: the routes serving pages answer with the matching rendered page,
+/// see [`render_error_pages`], the API with the message.
+#[derive(Debug)]
+pub(crate) enum BloggyError {
+    /// The request is invalid, e.g. an upload without a markdown file.
+    BadRequest(String),
+    /// Anything else which went wrong, only logged as it may leak details.
+    Internal(anyhow::Error),
+    NotFound,
+    /// The renderer is restarting after a failure or a stall.
+    Unavailable,
+}
+
+impl BloggyError {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            BloggyError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            BloggyError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            BloggyError::NotFound => StatusCode::NOT_FOUND,
+            BloggyError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl Display for BloggyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BloggyError::BadRequest(message) => f.write_str(message),
+            BloggyError::Internal(_) => f.write_str("Internal server error"),
+            BloggyError::NotFound => f.write_str("Not found"),
+            BloggyError::Unavailable => f.write_str("The renderer is restarting, retry later"),
+        }
+    }
+}
+
+// Note: `BloggyError` doesn't implement `std::error::Error` so that any error
+// converts to an internal one with `?`.
+impl<E> From<E> for BloggyError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(error: E) -> Self {
+        BloggyError::Internal(error.into())
+    }
+}
+
+/// Marks the responses built from a [`BloggyError`].
+#[derive(Clone, Copy, Debug)]
+struct ErrorResponse;
+
+impl IntoResponse for BloggyError {
+    fn into_response(self) -> Response {
+        if let BloggyError::Internal(error) = &self {
+            tracing::error!("{error:#}");
+        }
+
+        let mut response = (self.status(), self.to_string()).into_response();
+
+        if let BloggyError::Unavailable = self {
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(RENDERER_RESTART_DELAY.as_secs()),
+            );
+        }
+
+        response.extensions_mut().insert(ErrorResponse);
+
+        response
+    }
+}
+
+/// Answers with an internal error when a handler panics.
+pub(crate) fn handle_panic(payload: Box<dyn Any + Send + 'static>) -> Response {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or("unknown reason", |message| message)
+            .to_owned(),
+    };
+
+    BloggyError::Internal(anyhow!("a handler panicked: {message}")).into_response()
+}
+
+/// Replaces the message of the errors with the matching rendered page,
+/// keeping their status and headers.
+/// Note: errors without a page, e.g. bad requests, are left as is.
+pub(crate) async fn render_error_pages<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let encoding = get_preferred_encoding(request.headers());
+    let response = next.run(request).await;
+
+    if response.extensions().get::<ErrorResponse>().is_none() {
+        return response;
+    }
+
+    let site = state.site.load();
+    let Some(page) = site.error_pages.get(response.status()) else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    let (page_parts, body) =
+        get_variant_response(parts.status, page, encoding, HTML_CONTENT_TYPE).into_parts();
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.extend(page_parts.headers);
+
+    Response::from_parts(parts, body)
+}
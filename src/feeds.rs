@@ -0,0 +1,332 @@
+use std::{collections::HashMap, fmt::Write};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{
+    config::{Config, SortDirection},
+    date::{to_rfc2822, to_rfc3339},
+    file::encode_path_segment,
+    markdown::escape_html,
+    state::Post,
+    templates::sort_posts,
+};
+
+/// A feed advertised with a `<link rel="alternate">` tag.
+#[derive(Debug, Serialize)]
+pub(crate) struct FeedLink {
+    pub(crate) href: String,
+    pub(crate) mime_type: &'static str,
+    pub(crate) title: String,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedItem<'a> {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'a>>,
+    content_html: &'a str,
+    date_modified: String,
+    date_published: String,
+    id: String,
+    summary: &'a str,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    title: &'a str,
+    url: String,
+}
+
+/// See the [specification](https://www.jsonfeed.org/version/1.1/).
+#[derive(Debug, Serialize)]
+struct JsonFeed<'a> {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'a>>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    description: &'a str,
+    feed_url: String,
+    home_page_url: String,
+    items: Vec<JsonFeedItem<'a>>,
+    language: &'static str,
+    title: &'a str,
+    version: &'static str,
+}
+
+/// Gets the base URL without its trailing slash.
+pub(crate) fn get_base_url(config: &Config) -> &str {
+    config.site.base_url.trim_end_matches('/')
+}
+
+/// Gets the absolute URL of a post.
+pub(crate) fn get_post_url(config: &Config, post: &Post) -> String {
+    format!("{}/posts/{}", get_base_url(config), post.encoded_name)
+}
+
+/// Gets the posts to put in a feed, newest first.
+pub(crate) fn get_feed_posts(posts: &HashMap<String, Post>) -> Vec<&Post> {
+    let mut sorted_posts = posts.iter().collect::<Vec<(&String, &Post)>>();
+
+    sort_posts(&mut sorted_posts, SortDirection::Descending);
+
+    sorted_posts.into_iter().map(|(_, post)| post).collect()
+}
+
+/// Gets the author of a post, falling back to the author of the site.
+fn get_author<'a>(config: &'a Config, post: &'a Post) -> Option<&'a str> {
+    post.front_matter
+        .author
+        .as_deref()
+        .or(Some(config.site.author.as_str()))
+        .filter(|author| !author.is_empty())
+}
+
+/// Gets the links to the feeds, to be used for discovery in templates.
+/// Tag pages also advertise the feed of the tag.
+pub(crate) fn get_feed_links(config: &Config, tag: Option<&str>) -> Vec<FeedLink> {
+    let title = &config.site.title;
+    let mut feed_links = vec![
+        FeedLink {
+            href: "/feed.xml".to_owned(),
+            mime_type: "application/rss+xml",
+            title: format!("{title} (RSS)"),
+        },
+        FeedLink {
+            href: "/atom.xml".to_owned(),
+            mime_type: "application/atom+xml",
+            title: format!("{title} (Atom)"),
+        },
+        FeedLink {
+            href: "/feed.json".to_owned(),
+            mime_type: "application/feed+json",
+            title: format!("{title} (JSON Feed)"),
+        },
+    ];
+
+    if let Some(tag) = tag {
+        feed_links.push(FeedLink {
+            href: format!("/tags/{}/atom.xml", encode_path_segment(tag)),
+            mime_type: "application/atom+xml",
+            title: format!("{title}: {tag} (Atom)"),
+        });
+    }
+
+    feed_links
+}
+
+/// Gets the HTML to put in a feed entry, depending on the configuration.
+fn get_feed_contents<'a>(config: &Config, post: &'a Post) -> &'a str {
+    if config.feed.full_content {
+        &post.contents
+    } else {
+        &post.excerpt.html
+    }
+}
+
+/// Renders the RSS 2.0 feed.
+/// See the [specification](https://www.rssboard.org/rss-specification).
+pub(crate) fn render_rss(config: &Config, posts: &HashMap<String, Post>) -> String {
+    let base_url = get_base_url(config);
+    let feed_posts = get_feed_posts(posts);
+
+    let mut rss = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+
+    // Note: writing to a string can't fail.
+    let _ = write!(
+        rss,
+        r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><title>{}</title><link>{}/</link><description>{}</description><atom:link href="{}/feed.xml" rel="self" type="application/rss+xml"/>"#,
+        escape_html(&config.site.title),
+        escape_html(base_url),
+        escape_html(&config.site.description),
+        escape_html(base_url),
+    );
+
+    if let Some(last_updated) = feed_posts.iter().map(|post| post.updated).max() {
+        let _ = write!(
+            rss,
+            "<lastBuildDate>{}</lastBuildDate>",
+            to_rfc2822(last_updated)
+        );
+    }
+
+    for post in feed_posts {
+        let url = escape_html(&get_post_url(config, post));
+
+        let _ = write!(
+            rss,
+            r#"<item><title>{}</title><link>{url}</link><guid isPermaLink="true">{url}</guid><pubDate>{}</pubDate><description>{}</description>"#,
+            escape_html(&post.title),
+            to_rfc2822(post.published),
+            escape_html(get_feed_contents(config, post)),
+        );
+
+        for tag in &post.front_matter.tags {
+            let _ = write!(rss, "<category>{}</category>", escape_html(tag));
+        }
+
+        rss.push_str("</item>");
+    }
+
+    rss.push_str("</channel></rss>");
+
+    rss
+}
+
+/// Renders an Atom 1.0 feed, either for all posts or for a single tag.
+/// See the [specification](https://www.rfc-editor.org/rfc/rfc4287).
+pub(crate) fn render_atom(
+    config: &Config,
+    posts: &HashMap<String, Post>,
+    tag: Option<&str>,
+) -> String {
+    let base_url = get_base_url(config);
+    let feed_posts = get_feed_posts(posts)
+        .into_iter()
+        .filter(|post| tag.map_or(true, |tag| post.front_matter.tags.iter().any(|t| t == tag)))
+        .collect::<Vec<&Post>>();
+
+    let (title, alternate_url, self_url) = match tag {
+        Some(tag) => {
+            let encoded_tag = encode_path_segment(tag);
+
+            (
+                format!("{}: {tag}", config.site.title),
+                format!("{base_url}/tags/{encoded_tag}"),
+                format!("{base_url}/tags/{encoded_tag}/atom.xml"),
+            )
+        }
+        None => (
+            config.site.title.clone(),
+            format!("{base_url}/"),
+            format!("{base_url}/atom.xml"),
+        ),
+    };
+
+    // Note: the epoch keeps the output stable when there is no post.
+    let updated = feed_posts
+        .iter()
+        .map(|post| post.updated)
+        .max()
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+
+    let mut atom = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+
+    // Note: writing to a string can't fail.
+    let _ = write!(
+        atom,
+        r#"<feed xmlns="http://www.w3.org/2005/Atom"><id>{}</id><title>{}</title><updated>{}</updated><link href="{}"/><link href="{}" rel="self" type="application/atom+xml"/>"#,
+        escape_html(&self_url),
+        escape_html(&title),
+        to_rfc3339(updated),
+        escape_html(&alternate_url),
+        escape_html(&self_url),
+    );
+
+    if !config.site.description.is_empty() {
+        let _ = write!(
+            atom,
+            "<subtitle>{}</subtitle>",
+            escape_html(&config.site.description)
+        );
+    }
+
+    if !config.site.author.is_empty() {
+        let _ = write!(
+            atom,
+            "<author><name>{}</name></author>",
+            escape_html(&config.site.author)
+        );
+    }
+
+    for post in feed_posts {
+        let url = escape_html(&get_post_url(config, post));
+
+        let _ = write!(
+            atom,
+            r#"<entry><id>{url}</id><title>{}</title><link href="{url}"/><published>{}</published><updated>{}</updated>"#,
+            escape_html(&post.title),
+            to_rfc3339(post.published),
+            to_rfc3339(post.updated),
+        );
+
+        if let Some(author) = get_author(config, post) {
+            let _ = write!(
+                atom,
+                "<author><name>{}</name></author>",
+                escape_html(author)
+            );
+        }
+
+        let _ = write!(
+            atom,
+            r#"<summary type="html">{}</summary>"#,
+            escape_html(&post.excerpt.html)
+        );
+
+        if config.feed.full_content {
+            let _ = write!(
+                atom,
+                r#"<content type="html">{}</content>"#,
+                escape_html(&post.contents)
+            );
+        }
+
+        for tag in &post.front_matter.tags {
+            let _ = write!(atom, r#"<category term="{}"/>"#, escape_html(tag));
+        }
+
+        atom.push_str("</entry>");
+    }
+
+    atom.push_str("</feed>");
+
+    atom
+}
+
+/// Renders the JSON Feed 1.1 feed.
+pub(crate) fn render_json_feed(config: &Config, posts: &HashMap<String, Post>) -> String {
+    let base_url = get_base_url(config);
+    let site_authors = Some(config.site.author.as_str())
+        .filter(|author| !author.is_empty())
+        .map(|name| JsonFeedAuthor { name })
+        .into_iter()
+        .collect();
+
+    let items = get_feed_posts(posts)
+        .into_iter()
+        .map(|post| {
+            let url = get_post_url(config, post);
+
+            JsonFeedItem {
+                authors: get_author(config, post)
+                    .map(|name| JsonFeedAuthor { name })
+                    .into_iter()
+                    .collect(),
+                content_html: get_feed_contents(config, post),
+                date_modified: to_rfc3339(post.updated),
+                date_published: to_rfc3339(post.published),
+                id: url.clone(),
+                summary: &post.excerpt.text,
+                tags: &post.front_matter.tags,
+                title: &post.title,
+                url,
+            }
+        })
+        .collect();
+
+    let json_feed = JsonFeed {
+        authors: site_authors,
+        description: &config.site.description,
+        feed_url: format!("{base_url}/feed.json"),
+        home_page_url: format!("{base_url}/"),
+        items,
+        language: "en",
+        title: &config.site.title,
+        version: "https://jsonfeed.org/version/1.1",
+    };
+
+    // Note: this can't fail since every key is a string.
+    serde_json::to_string(&json_feed).unwrap_or_default()
+}
@@ -5,6 +5,8 @@ use std::{
 
 use url::form_urlencoded;
 
+use crate::markdown::get_markdown_file_name;
+
 pub(crate) struct FileDescriptor {
     pub(crate) encoded_name: String,
     pub(crate) original_name: String,
@@ -12,7 +14,8 @@ pub(crate) struct FileDescriptor {
 }
 
 /// Gets the file descriptor from the provided paths.
-/// Returns the URI encoded file name and the path.
+/// Returns the file name, also encoded as a path segment for the URLs of the
+/// post, and the path.
 pub(crate) fn get_file_descriptor_from_paths<P>(paths: &[P]) -> Option<FileDescriptor>
 where
     P: AsRef<Path>,
@@ -26,11 +29,38 @@ where
         path.file_stem()
             .and_then(OsStr::to_str)
             .map(|name| FileDescriptor {
-                encoded_name: form_urlencoded::Serializer::new(String::new())
-                    .append_key_only(name)
-                    .finish(),
+                encoded_name: encode_path_segment(name),
                 original_name: name.to_owned(),
                 path_buf,
             })
     })
 }
+
+/// Checks that the path is a markdown file and not a hidden one, e.g. a swap
+/// file or a temporary file written by an editor.
+pub(crate) fn is_post_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map_or(false, |name| {
+            !name.starts_with('.') && get_markdown_file_name(name).is_some()
+        })
+}
+
+/// Percent-encodes a name to be used as a path segment.
+/// Note: spaces are encoded as `%20` since `+` is not decoded in paths.
+pub(crate) fn encode_path_segment(name: &str) -> String {
+    form_urlencoded::byte_serialize(name.as_bytes())
+        .map(|chunk| if chunk == "+" { "%20" } else { chunk })
+        .collect()
+}
+
+/// Turns a title into a lowercase, dash separated slug.
+/// Note: non ASCII alphanumeric characters are kept as is.
+pub(crate) fn slugify(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join("-")
+}
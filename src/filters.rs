@@ -0,0 +1,315 @@
+use std::path::{Component, Path, PathBuf};
+
+use minijinja::{value::Value, Environment, Error, ErrorKind};
+use time::format_description;
+
+use crate::{
+    cache::get_content_hash,
+    config::Config,
+    date::parse_date,
+    file::{encode_path_segment, slugify},
+    markdown::inline_markdown_to_html,
+    templates::get_page_url,
+};
+
+/// Format of the `date` filter when none is given, e.g. `2023-05-01`.
+const DEFAULT_DATE_FORMAT: &str = "[year]-[month]-[day]";
+
+/// Number of words kept by the `truncate_words` filter by default.
+const DEFAULT_WORD_COUNT: usize = 30;
+
+/// Reading speed used by the `reading_time` filter.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Length of the fingerprint of the assets.
+const FINGERPRINT_LENGTH: usize = 12;
+
+/// Registers the filters and the functions available to the templates:
+/// - `value|date(format)` formats a date, see the
+///   [format description](https://time-rs.github.io/book/api/format-description.html).
+/// - `value|truncate_words(count)` keeps the first words of a text.
+/// - `value|markdown` renders inline markdown.
+/// - `value|slugify` turns a text into a slug.
+/// - `value|reading_time` estimates the reading time of a text in minutes.
+/// - `url_for(route, params)` builds the path of a route, e.g.
+///   `url_for("post", name=post.original_name)`.
+/// - `asset(path)` builds the fingerprinted path of a public asset, e.g.
+///   `asset("output.css")`.
+pub(crate) fn add_filters(env: &mut Environment<'_>, config: &Config) {
+    let public_dir = config.paths.public.clone();
+
+    env.add_filter("date", date);
+    env.add_filter("markdown", markdown);
+    env.add_filter("reading_time", reading_time);
+    env.add_filter("slugify", slugify_filter);
+    env.add_filter("truncate_words", truncate_words);
+    // Note: paths are already percent-encoded and don't need escaping.
+    env.add_function("asset", move |path: &str| {
+        asset(&public_dir, path).map(Value::from_safe_string)
+    });
+    env.add_function("url_for", |route: &str, params: Option<&Value>| {
+        url_for(route, params).map(Value::from_safe_string)
+    });
+}
+
+/// Formats a date, given as e.g. RFC 3339.
+fn date(value: &str, format: Option<&str>) -> Result<String, Error> {
+    let date = parse_date(value).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid date: {value:?}"),
+        )
+    })?;
+    let format =
+        format_description::parse(format.unwrap_or(DEFAULT_DATE_FORMAT)).map_err(|error| {
+            Error::new(ErrorKind::InvalidOperation, "invalid date format").with_source(error)
+        })?;
+
+    date.format(&format)
+        .map_err(|error| Error::new(ErrorKind::InvalidOperation, "invalid date").with_source(error))
+}
+
+/// Keeps the first words of a text, followed by an ellipsis if truncated.
+fn truncate_words(value: &str, count: Option<usize>) -> String {
+    let count = count.unwrap_or(DEFAULT_WORD_COUNT);
+    let words = value.split_whitespace().collect::<Vec<&str>>();
+
+    if words.len() <= count {
+        return words.join(" ");
+    }
+
+    format!("{}…", words[..count].join(" "))
+}
+
+/// Renders inline markdown, e.g. a title.
+fn markdown(value: &str) -> Value {
+    Value::from_safe_string(inline_markdown_to_html(value))
+}
+
+fn slugify_filter(value: &str) -> String {
+    slugify(value)
+}
+
+/// Estimates the reading time of a text or of some HTML, in minutes.
+/// Note: it is always at least a minute.
+fn reading_time(value: &str) -> usize {
+    let word_count = strip_tags(value).split_whitespace().count();
+
+    ((word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE).max(1)
+}
+
+/// Removes the tags from some HTML, keeping their text.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for character in html.chars() {
+        match character {
+            '<' => in_tag = true,
+            // Note: tags are replaced by a space to keep the words apart.
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(character),
+            _ => (),
+        }
+    }
+
+    text
+}
+
+/// Builds the path of a route.
+/// The parameters are either a map or keyword arguments.
+fn url_for(route: &str, params: Option<&Value>) -> Result<String, Error> {
+    let get_param = |name: &str| {
+        params
+            .map(|params| params.get_attr(name))
+            .transpose()?
+            .filter(|value| !value.is_undefined() && !value.is_none())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::MissingArgument,
+                    format!("the {route:?} route needs a {name:?} parameter"),
+                )
+            })
+    };
+
+    match route {
+        "about" => Ok("/about".to_owned()),
+        "atom" => Ok("/atom.xml".to_owned()),
+        "json_feed" => Ok("/feed.json".to_owned()),
+        "page" => Ok(get_page_url(usize::try_from(get_param("page")?)?)),
+        "post" => Ok(format!(
+            "/posts/{}",
+            encode_path_segment(&get_param("name")?.to_string())
+        )),
+        "root" => Ok("/".to_owned()),
+        "rss" => Ok("/feed.xml".to_owned()),
+        "sitemap" => Ok("/sitemap.xml".to_owned()),
+        "tag" => Ok(format!(
+            "/tags/{}",
+            encode_path_segment(&get_param("name")?.to_string())
+        )),
+        "tag_atom" => Ok(format!(
+            "/tags/{}/atom.xml",
+            encode_path_segment(&get_param("name")?.to_string())
+        )),
+        "tags" => Ok("/tags".to_owned()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("unknown route: {route:?}"),
+        )),
+    }
+}
+
+/// Builds the path of a public asset, with a fingerprint of its contents so
+/// that it can be cached for good.
+/// Note: the templates are rendered again when the public directory changes,
+/// which keeps the fingerprints up to date.
+fn asset(public_dir: &Path, path: &str) -> Result<String, Error> {
+    let relative_path = PathBuf::from(path.trim_start_matches('/'));
+
+    // Note: assets must not escape the public directory.
+    if !relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid asset path: {path:?}"),
+        ));
+    }
+
+    let url = format!(
+        "/public/{}",
+        relative_path
+            .components()
+            .map(|component| encode_path_segment(&component.as_os_str().to_string_lossy()))
+            .collect::<Vec<String>>()
+            .join("/")
+    );
+
+    // Note: a missing asset is not worth failing the whole page.
+    let contents = match std::fs::read(public_dir.join(&relative_path)) {
+        Ok(contents) => contents,
+        Err(error) => {
+            tracing::warn!("could not fingerprint the asset {path:?}: {error}");
+
+            return Ok(url);
+        }
+    };
+
+    Ok(format!(
+        "{url}?v={}",
+        &get_content_hash(&contents)[..FINGERPRINT_LENGTH]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use minijinja::context;
+
+    use super::*;
+
+    fn render(template: &str) -> String {
+        let mut env = Environment::new();
+
+        add_filters(&mut env, &Config::default());
+
+        env.render_str(template, context!(title => "Hello *world*"))
+            .unwrap()
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(date("2023-05-01T10:20:30Z", None).unwrap(), "2023-05-01");
+        assert_eq!(
+            date("2023-05-01", Some("[day]/[month]/[year] [hour]:[minute]")).unwrap(),
+            "01/05/2023 00:00"
+        );
+        assert!(date("yesterday", None).is_err());
+        assert!(date("2023-05-01", Some("[nope]")).is_err());
+    }
+
+    #[test]
+    fn truncates_words() {
+        assert_eq!(truncate_words("one two  three", Some(2)), "one two…");
+        assert_eq!(truncate_words("one two", Some(2)), "one two");
+        assert_eq!(truncate_words("", None), "");
+    }
+
+    #[test]
+    fn renders_inline_markdown() {
+        assert_eq!(
+            markdown("Hello *world*").to_string(),
+            "Hello <em>world</em>"
+        );
+        assert_eq!(render("{{ title|markdown }}"), "Hello <em>world</em>");
+    }
+
+    #[test]
+    fn slugifies() {
+        assert_eq!(
+            slugify_filter("Hello, World: Take 2!"),
+            "hello-world-take-2"
+        );
+        assert_eq!(render("{{ title|slugify }}"), "hello-world");
+    }
+
+    #[test]
+    fn estimates_reading_time() {
+        assert_eq!(reading_time(""), 1);
+        assert_eq!(reading_time(&"word ".repeat(200)), 1);
+        assert_eq!(reading_time(&"word ".repeat(201)), 2);
+        assert_eq!(reading_time(&"<p>one</p><p>two</p>".repeat(150)), 2);
+    }
+
+    #[test]
+    fn builds_urls() {
+        assert_eq!(url_for("root", None).unwrap(), "/");
+        assert_eq!(
+            render(r#"{{ url_for("post", name="a b") }} {{ url_for("page", {"page": 2}) }}"#),
+            "/posts/a%20b /page/2"
+        );
+        assert_eq!(
+            render(r#"{{ url_for("tag_atom", name="c#") }}"#),
+            "/tags/c%23/atom.xml"
+        );
+        assert_eq!(
+            url_for("tag", None).unwrap_err().kind(),
+            ErrorKind::MissingArgument
+        );
+        assert!(url_for("nope", None).is_err());
+    }
+
+    #[test]
+    fn fingerprints_assets() {
+        let public_dir = std::env::temp_dir().join(format!("bloggy-assets-{}", std::process::id()));
+
+        fs::create_dir_all(public_dir.join("css")).unwrap();
+        fs::write(public_dir.join("css/site.css"), "body {}").unwrap();
+
+        let path = asset(&public_dir, "css/site.css").unwrap();
+
+        fs::write(public_dir.join("css/site.css"), "body { margin: 0 }").unwrap();
+
+        let updated_path = asset(&public_dir, "/css/site.css").unwrap();
+
+        fs::remove_dir_all(&public_dir).unwrap();
+
+        assert!(path.starts_with("/public/css/site.css?v="));
+        assert_eq!(
+            path.len(),
+            "/public/css/site.css?v=".len() + FINGERPRINT_LENGTH
+        );
+        assert_ne!(path, updated_path);
+        assert!(asset(&public_dir, "../secret").is_err());
+        assert_eq!(
+            asset(&public_dir, "missing.css").unwrap(),
+            "/public/missing.css"
+        );
+    }
+}
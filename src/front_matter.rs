@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+
+const YAML_DELIMITER: &str = "---";
+const TOML_DELIMITER: &str = "+++";
+
+/// Metadata declared at the top of a post, either as YAML between `---`
+/// lines or as TOML between `+++` lines.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct FrontMatter {
+    pub(crate) author: Option<String>,
+    #[serde(default, with = "crate::date::option")]
+    pub(crate) date: Option<OffsetDateTime>,
+    pub(crate) description: Option<String>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    /// Template to render the post with instead of the default one.
+    pub(crate) template: Option<String>,
+    pub(crate) title: Option<String>,
+    #[serde(default, with = "crate::date::option")]
+    pub(crate) updated: Option<OffsetDateTime>,
+    /// Any other key, kept as is for the templates.
+    #[serde(flatten)]
+    pub(crate) extra: Map<String, Value>,
+}
+
+/// Splits the front matter from the contents of a post.
+/// Returns the parsed front matter and the remaining markdown.
+pub(crate) fn parse_front_matter(contents: &str) -> Result<(FrontMatter, &str)> {
+    // Ignore a potential byte order mark.
+    let contents = contents.trim_start_matches('\u{feff}');
+
+    let (delimiter, is_toml) = if starts_with_delimiter(contents, YAML_DELIMITER) {
+        (YAML_DELIMITER, false)
+    } else if starts_with_delimiter(contents, TOML_DELIMITER) {
+        (TOML_DELIMITER, true)
+    } else {
+        return Ok((FrontMatter::default(), contents));
+    };
+
+    // Skip the opening delimiter line.
+    let rest = contents[delimiter.len()..].trim_start_matches(['\r', '\n']);
+
+    let mut offset = 0;
+    let (raw, body) = loop {
+        let Some(line) = rest[offset..].split_inclusive('\n').next() else {
+            anyhow::bail!("unclosed front matter, expected a closing {delimiter:?}");
+        };
+
+        if line.trim_end() == delimiter {
+            break (&rest[..offset], &rest[offset + line.len()..]);
+        }
+
+        offset += line.len();
+    };
+
+    let value = if is_toml {
+        toml_to_json(toml::from_str(raw).context("invalid TOML front matter")?)
+    } else {
+        serde_yaml::from_str(raw).context("invalid YAML front matter")?
+    };
+
+    // An empty front matter is valid.
+    let front_matter = if value.is_null() {
+        FrontMatter::default()
+    } else {
+        serde_json::from_value(value).context("invalid front matter")?
+    };
+
+    // Note: each tag is a path segment of its page, both when served and when
+    // exported.
+    if let Some(tag) = front_matter
+        .tags
+        .iter()
+        .find(|tag| tag.is_empty() || *tag == "." || *tag == ".." || tag.contains(['/', '\\']))
+    {
+        anyhow::bail!("invalid tag {tag:?}, tags can't be empty, contain slashes or be dots");
+    }
+
+    Ok((front_matter, body))
+}
+
+fn starts_with_delimiter(contents: &str, delimiter: &str) -> bool {
+    contents
+        .lines()
+        .next()
+        .map_or(false, |line| line.trim_end() == delimiter)
+}
+
+/// Converts a TOML value to a JSON one.
+/// Note: TOML datetimes have no JSON counterpart and are kept as strings.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Boolean(boolean) => Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Float(float) => {
+            serde_json::Number::from_f64(float).map_or(Value::Null, Value::Number)
+        }
+        toml::Value::Integer(integer) => Value::Number(integer.into()),
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn parses_yaml() {
+        let (front_matter, body) =
+            parse_front_matter("---\ntitle: Hello\ntags: [rust]\nmood: happy\n---\n# Hello\n")
+                .unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(front_matter.tags, ["rust"]);
+        assert_eq!(front_matter.extra["mood"], "happy");
+        assert_eq!(body, "# Hello\n");
+    }
+
+    #[test]
+    fn keeps_posts_without_front_matter() {
+        let (front_matter, body) = parse_front_matter("# Hello\n---\n").unwrap();
+
+        assert!(front_matter.title.is_none());
+        assert_eq!(body, "# Hello\n---\n");
+        assert!(parse_front_matter("---\n---\n").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn handles_crlf_and_bom() {
+        let (front_matter, body) =
+            parse_front_matter("\u{feff}---\r\ntitle: Hello\r\n---\r\nBody\r\n").unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(body, "Body\r\n");
+
+        let (front_matter, body) =
+            parse_front_matter("\u{feff}+++\r\ntitle = \"Hello\"\r\n+++\r\nBody").unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn rejects_invalid_front_matter() {
+        assert!(parse_front_matter("---\ntitle: Hello\n").is_err());
+        assert!(parse_front_matter("+++\ntitle = \"Hello\"\n---\n").is_err());
+        assert!(parse_front_matter("---\ntitle: [\n---\n").is_err());
+        assert!(parse_front_matter("---\ndate: yesterday\n---\n").is_err());
+        assert!(parse_front_matter("---\ntags: [ci/cd]\n---\n").is_err());
+        assert!(parse_front_matter("+++\ntags = [\"..\"]\n+++\n").is_err());
+    }
+
+    #[test]
+    fn parses_toml_datetimes() {
+        let (front_matter, body) = parse_front_matter(
+            "+++\ndate = 2023-05-01T10:20:30Z\nupdated = 2023-05-02\nevent = 1979-05-27T07:32:00Z\n+++\nBody",
+        )
+        .unwrap();
+
+        assert_eq!(front_matter.date, Some(datetime!(2023-05-01 10:20:30 UTC)));
+        assert_eq!(front_matter.updated, Some(datetime!(2023-05-02 0:00 UTC)));
+        assert_eq!(front_matter.extra["event"], "1979-05-27T07:32:00Z");
+        assert_eq!(body, "Body");
+    }
+}
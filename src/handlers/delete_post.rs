@@ -1,35 +1,37 @@
-use std::path::{Path as StdPath, PathBuf};
+use std::path::PathBuf;
 
+use anyhow::Context;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
 };
 use tokio::fs::remove_file;
 
-use crate::state::AppState;
+use crate::{error::BloggyError, state::AppState};
 
 use super::params::PostParams;
 
+/// Removes the file of a post.
+/// Note: the watcher unpublishes the post once the file is gone.
 pub(crate) async fn delete_post(
     Path(PostParams { id }): Path<PostParams>,
     State(state): State<AppState>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    let mut posts_guard = state.posts.lock().await;
+) -> Result<StatusCode, BloggyError> {
+    state.ensure_renderer_running()?;
 
-    match posts_guard.remove(&id) {
-        Some(_) => {
-            let mut filename = PathBuf::new();
-            filename.push(id);
-            filename.set_extension("md");
+    if !state.site.load().posts.contains_key(&id) {
+        return Err(BloggyError::NotFound);
+    }
 
-            let file_path = StdPath::new("./posts").join(filename);
+    let mut filename = PathBuf::new();
+    filename.push(id);
+    filename.set_extension("md");
 
-            remove_file(file_path)
-                .await
-                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    let file_path = state.config.paths.posts.join(filename);
 
-            Ok(StatusCode::OK)
-        }
-        None => Err((StatusCode::NOT_FOUND, "File not found".to_owned())),
-    }
+    remove_file(&file_path)
+        .await
+        .with_context(|| format!("failed to remove {}", file_path.display()))?;
+
+    Ok(StatusCode::OK)
 }
@@ -1,9 +1,26 @@
-use axum::{extract::State, response::Html};
+use axum::{extract::State, http::HeaderMap, response::Response};
 
-use crate::state::AppState;
+use crate::{
+    cache::{get_page_response, HTML_CONTENT_TYPE},
+    error::BloggyError,
+    state::AppState,
+};
 
-pub(crate) async fn get_about(State(state): State<AppState>) -> Html<String> {
-    let about = state.about_template.lock().await;
+pub(crate) async fn get_about(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, BloggyError> {
+    let about = state.site.load().about.clone();
 
-    Html(about.to_owned())
+    // Note: the about page is optional.
+    if about.body.is_empty() {
+        return Err(BloggyError::NotFound);
+    }
+
+    Ok(get_page_response(
+        &headers,
+        &about,
+        HTML_CONTENT_TYPE,
+        &state.config.cache.posts,
+    ))
 }
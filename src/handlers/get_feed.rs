@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+
+use crate::{
+    cache::get_page_response, error::BloggyError, handlers::params::TagParams, state::AppState,
+};
+
+pub(crate) async fn get_rss(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    get_page_response(
+        &headers,
+        &state.site.load().derived_pages.rss,
+        "application/rss+xml; charset=utf-8",
+        &state.config.cache.feeds,
+    )
+}
+
+pub(crate) async fn get_atom(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    get_page_response(
+        &headers,
+        &state.site.load().derived_pages.atom,
+        "application/atom+xml; charset=utf-8",
+        &state.config.cache.feeds,
+    )
+}
+
+pub(crate) async fn get_json_feed(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    get_page_response(
+        &headers,
+        &state.site.load().derived_pages.json_feed,
+        "application/feed+json; charset=utf-8",
+        &state.config.cache.feeds,
+    )
+}
+
+pub(crate) async fn get_tag_atom(
+    Path(TagParams { tag }): Path<TagParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, BloggyError> {
+    let site = state.site.load();
+    let tag_atom = site
+        .derived_pages
+        .tag_atoms
+        .get(&tag)
+        .ok_or(BloggyError::NotFound)?;
+
+    Ok(get_page_response(
+        &headers,
+        tag_atom,
+        "application/atom+xml; charset=utf-8",
+        &state.config.cache.feeds,
+    ))
+}
@@ -1,22 +1,28 @@
 use axum::{
     extract::{Path, State},
-    response::Html,
+    http::HeaderMap,
+    response::Response,
 };
 
-use crate::{handlers::params::PostParams, state::AppState};
+use crate::{
+    cache::{get_page_response, HTML_CONTENT_TYPE},
+    error::BloggyError,
+    handlers::params::PostParams,
+    state::AppState,
+};
 
 pub(crate) async fn get_post(
     Path(PostParams { id }): Path<PostParams>,
     State(state): State<AppState>,
-) -> Html<String> {
-    let posts_guard = state.posts.lock().await;
-    let maybe_post = posts_guard.get(&id);
-
-    if let Some(post) = maybe_post {
-        Html(post.rendered_template.clone())
-    } else {
-        let not_found = state.not_found_template.lock().await;
+    headers: HeaderMap,
+) -> Result<Response, BloggyError> {
+    let site = state.site.load();
+    let post = site.posts.get(&id).ok_or(BloggyError::NotFound)?;
 
-        Html(not_found.to_owned())
-    }
+    Ok(get_page_response(
+        &headers,
+        post,
+        HTML_CONTENT_TYPE,
+        &state.config.cache.posts,
+    ))
 }
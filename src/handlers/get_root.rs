@@ -1,10 +1,48 @@
-use axum::{extract::State, response::Html};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
 
-use crate::state::AppState;
+use crate::{
+    cache::{get_page_response, HTML_CONTENT_TYPE},
+    error::BloggyError,
+    handlers::params::{PageParams, PageQuery},
+    state::AppState,
+};
 
-pub(crate) async fn get_root(State(state): State<AppState>) -> Html<String> {
-    let root_template_guard = state.root_template.lock().await;
-    let root_template = root_template_guard.to_owned();
+pub(crate) async fn get_root(
+    Query(PageQuery { page }): Query<PageQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, BloggyError> {
+    get_root_page(page.unwrap_or(1), &state, &headers)
+}
+
+pub(crate) async fn get_page(
+    Path(PageParams { page }): Path<PageParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, BloggyError> {
+    get_root_page(page, &state, &headers)
+}
+
+fn get_root_page(
+    page: usize,
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Response, BloggyError> {
+    let site = state.site.load();
+    // Note: pages start at 1.
+    let root_page = page
+        .checked_sub(1)
+        .and_then(|index| site.derived_pages.root_pages.get(index))
+        .ok_or(BloggyError::NotFound)?;
 
-    Html(root_template)
+    Ok(get_page_response(
+        headers,
+        root_page,
+        HTML_CONTENT_TYPE,
+        &state.config.cache.pages,
+    ))
 }
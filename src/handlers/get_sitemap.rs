@@ -0,0 +1,21 @@
+use axum::{extract::State, http::HeaderMap, response::Response};
+
+use crate::{cache::get_page_response, state::AppState};
+
+pub(crate) async fn get_sitemap(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    get_page_response(
+        &headers,
+        &state.site.load().derived_pages.sitemap,
+        "application/xml; charset=utf-8",
+        &state.config.cache.feeds,
+    )
+}
+
+pub(crate) async fn get_robots(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    get_page_response(
+        &headers,
+        &state.site.load().derived_pages.robots,
+        "text/plain; charset=utf-8",
+        &state.config.cache.feeds,
+    )
+}
@@ -0,0 +1,32 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+
+use crate::{
+    cache::{get_page_response, HTML_CONTENT_TYPE},
+    error::BloggyError,
+    handlers::params::TagParams,
+    state::AppState,
+};
+
+pub(crate) async fn get_tag(
+    Path(TagParams { tag }): Path<TagParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, BloggyError> {
+    let site = state.site.load();
+    let tag_page = site
+        .derived_pages
+        .tags
+        .get(&tag)
+        .ok_or(BloggyError::NotFound)?;
+
+    Ok(get_page_response(
+        &headers,
+        tag_page,
+        HTML_CONTENT_TYPE,
+        &state.config.cache.pages,
+    ))
+}
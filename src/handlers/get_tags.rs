@@ -0,0 +1,15 @@
+use axum::{extract::State, http::HeaderMap, response::Response};
+
+use crate::{
+    cache::{get_page_response, HTML_CONTENT_TYPE},
+    state::AppState,
+};
+
+pub(crate) async fn get_tags(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    get_page_response(
+        &headers,
+        &state.site.load().derived_pages.tag_index,
+        HTML_CONTENT_TYPE,
+        &state.config.cache.pages,
+    )
+}
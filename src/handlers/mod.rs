@@ -1,7 +1,11 @@
 pub(crate) mod delete_post;
 pub(crate) mod get_about;
+pub(crate) mod get_feed;
 pub(crate) mod get_post;
 pub(crate) mod get_root;
+pub(crate) mod get_sitemap;
+pub(crate) mod get_tag;
+pub(crate) mod get_tags;
 pub(crate) mod not_found;
 pub(crate) mod params;
 pub(crate) mod upload_post;
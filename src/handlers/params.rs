@@ -4,3 +4,18 @@ use serde::Deserialize;
 pub(crate) struct PostParams {
     pub(crate) id: String,
 }
+
+#[derive(Deserialize)]
+pub(crate) struct TagParams {
+    pub(crate) tag: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PageParams {
+    pub(crate) page: usize,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PageQuery {
+    pub(crate) page: Option<usize>,
+}
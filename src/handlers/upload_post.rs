@@ -1,57 +1,52 @@
-use std::path::Path;
-
-use axum::{extract::Multipart, http::StatusCode};
+use anyhow::Context;
+use axum::{
+    extract::{Multipart, State},
+    http::StatusCode,
+};
 use tokio::{fs::File, io::AsyncWriteExt};
 
-use crate::markdown::get_markdown_file_name;
+use crate::{error::BloggyError, markdown::get_markdown_file_name, state::AppState};
 
 /// See the [multipart documentation](https://docs.rs/axum/latest/src/axum/extract/multipart.rs.html#248).
 pub(crate) async fn upload_post(
+    State(state): State<AppState>,
     mut multipart: Multipart,
-) -> Result<StatusCode, (StatusCode, String)> {
+) -> Result<StatusCode, BloggyError> {
+    state.ensure_renderer_running()?;
+
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?
+        .map_err(|err| BloggyError::BadRequest(err.to_string()))?
     {
-        let markdown_file_name = field.file_name().and_then(get_markdown_file_name);
+        let Some(markdown_file_name) = field
+            .file_name()
+            .and_then(get_markdown_file_name)
+            .map(ToOwned::to_owned)
+        else {
+            return Err(BloggyError::BadRequest("Invalid markdown file".to_owned()));
+        };
+
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|_| BloggyError::BadRequest("Empty file".to_owned()))?;
 
-        if markdown_file_name.is_none() {
-            return Err((StatusCode::BAD_REQUEST, "Invalid markdown file".to_owned()));
+        if bytes.is_empty() {
+            return Err(BloggyError::BadRequest(format!(
+                "Empty file: {markdown_file_name:?}"
+            )));
         }
 
-        // We can safely unwrap here since this is already handled above.
-        let markdown_file_name = markdown_file_name.unwrap().to_owned();
-
-        match field.bytes().await {
-            Ok(bytes) => {
-                if bytes.is_empty() {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        format!("Empty file: {markdown_file_name:?}"),
-                    ));
-                }
-
-                let file_path = Path::new("./posts").join(markdown_file_name);
-
-                match File::create(file_path).await {
-                    Ok(mut file) => {
-                        if file.write_all(&bytes).await.is_err() {
-                            return Err((
-                                StatusCode::BAD_REQUEST,
-                                "File creation failed".to_owned(),
-                            ));
-                        }
-                    }
-                    Err(_) => {
-                        return Err((StatusCode::BAD_REQUEST, "File creation failed".to_owned()));
-                    }
-                };
-            }
-            Err(_) => {
-                return Err((StatusCode::BAD_REQUEST, "Empty file".to_owned()));
-            }
-        };
+        let file_path = state.config.paths.posts.join(markdown_file_name);
+
+        let mut file = File::create(&file_path)
+            .await
+            .with_context(|| format!("failed to create {}", file_path.display()))?;
+
+        file.write_all(&bytes)
+            .await
+            .with_context(|| format!("failed to write {}", file_path.display()))?;
     }
 
     Ok(StatusCode::CREATED)
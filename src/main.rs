@@ -4,23 +4,32 @@
 #![warn(missing_debug_implementations, missing_docs, unreachable_pub)]
 #![deny(clippy::pedantic, clippy::clone_on_ref_ptr)]
 
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use std::sync::Arc;
 
 use anyhow::Result;
-use axum_server::tls_rustls::RustlsConfig;
-use tokio::sync::Mutex;
+use clap::Parser;
 
 use crate::{
-    app::create_app,
-    state::{AppState, Post},
-    templates::{generate_initial_templates, templates_manager, InitialTemplates},
-    watcher::async_watch,
+    cli::{Cli, Command},
+    commands::{build::build, check::check, new::new_post, serve::serve},
+    config::Config,
+    state::Post,
 };
 
 mod app;
+mod cache;
+mod cli;
+mod commands;
+mod config;
+mod date;
+mod error;
+mod feeds;
 mod file;
+mod filters;
+mod front_matter;
 mod handlers;
 mod markdown;
+mod sitemap;
 mod state;
 mod templates;
 mod watcher;
@@ -30,43 +39,42 @@ async fn main() -> Result<()> {
     // Initialize tracing.
     tracing_subscriber::fmt::init();
 
-    // Add certificate and private key.
-    let config = RustlsConfig::from_pem_file("./cert/certificate.pem", "./cert/key.pem").await?;
-
-    let posts = Arc::new(Mutex::new(HashMap::<String, Post>::new()));
-
-    let sender = templates_manager(Arc::clone(&posts)).await?;
-
-    // Get all templates.
-    let InitialTemplates {
-        about_template,
-        not_found_template,
-        root_template,
-    } = generate_initial_templates(Arc::clone(&posts), sender.clone()).await?;
-
-    let about_template = Arc::new(Mutex::new(about_template));
-    let not_found_template = Arc::new(Mutex::new(not_found_template));
-    let root_template = Arc::new(Mutex::new(root_template));
-
-    let posts_clone = Arc::clone(&posts);
-    let root_template_clone = Arc::clone(&root_template);
-
-    // Spawn the watcher task.
-    tokio::spawn(async move {
-        async_watch("./posts", posts_clone, root_template_clone, sender).await?;
-
-        Ok::<_, anyhow::Error>(())
-    });
-
-    let state = AppState::new(about_template, not_found_template, posts, root_template);
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3443));
-
-    tracing::debug!("listening on {}", addr);
-
-    axum_server::bind_rustls(addr, config)
-        .serve(create_app(state).into_make_service())
-        .await?;
-
-    Ok(())
+    let Cli {
+        config: config_path,
+        posts_dir,
+        command,
+    } = Cli::parse();
+
+    // Load and validate the configuration.
+    let mut config = Config::load(&config_path)?;
+
+    // The command-line arguments take precedence over the configuration.
+    if let Some(posts_dir) = posts_dir {
+        config.paths.posts = posts_dir;
+    }
+
+    match command {
+        Command::Build { output } => {
+            if let Some(output) = output {
+                config.paths.output = output;
+            }
+
+            build(Arc::new(config)).await
+        }
+        Command::Check => check(Arc::new(config)).await,
+        Command::New { title, tags } => {
+            let path = new_post(&config, &title, &tags).await?;
+
+            println!("{}", path.display());
+
+            Ok(())
+        }
+        Command::Serve { port } => {
+            if let Some(port) = port {
+                config.server.port = port;
+            }
+
+            serve(Arc::new(config)).await
+        }
+    }
 }
@@ -1,15 +1,35 @@
-use std::{ffi::OsStr, path::Path};
+use std::{cell::RefCell, ffi::OsStr, path::Path};
 
+use anyhow::{bail, Context, Result};
 use comrak::{
-    markdown_to_html_with_plugins, plugins::syntect::SyntectAdapterBuilder, ComrakOptions,
-    ComrakPlugins,
+    arena_tree::Node,
+    format_html, markdown_to_html, markdown_to_html_with_plugins,
+    nodes::{Ast, NodeCode, NodeValue},
+    parse_document,
+    plugins::syntect::{SyntectAdapter, SyntectAdapterBuilder},
+    Arena, ComrakOptions, ComrakPlugins,
 };
+use serde::Serialize;
 use syntect::highlighting::ThemeSet;
 
-pub(crate) fn contents_to_markdown(contents: &str) -> String {
-    let adapter_builder = SyntectAdapterBuilder::new();
+/// Marker splitting the excerpt from the rest of a post.
+const MORE_MARKER: &str = "<!-- more -->";
+
+/// Name of the syntax highlighting theme, i.e. `theme.tmTheme` in the themes
+/// directory.
+const THEME_NAME: &str = "theme";
+
+/// Maximum length of an excerpt taken from the first paragraph.
+const EXCERPT_MAX_LENGTH: usize = 280;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct Excerpt {
+    pub(crate) html: String,
+    pub(crate) text: String,
+}
+
+fn get_options() -> ComrakOptions {
     let mut options = ComrakOptions::default();
-    let mut plugins = ComrakPlugins::default();
 
     options.extension.autolink = true;
     options.extension.header_ids = Some("header-".to_owned());
@@ -18,13 +38,62 @@ pub(crate) fn contents_to_markdown(contents: &str) -> String {
     options.extension.tasklist = true;
     options.render.github_pre_lang = true;
 
-    let mut theme_set = ThemeSet::new();
-    theme_set.add_from_folder("./themes").unwrap();
-    let adapter = adapter_builder.theme_set(theme_set).theme("theme").build();
+    options
+}
+
+/// Renders the posts to HTML.
+/// Note: loading the syntaxes and the themes is slow, so a single renderer is
+/// built upfront and shared, then built again when the themes change.
+#[derive(Debug)]
+pub(crate) struct MarkdownRenderer {
+    adapter: SyntectAdapter<'static>,
+    options: ComrakOptions,
+}
 
-    plugins.render.codefence_syntax_highlighter = Some(&adapter);
+impl MarkdownRenderer {
+    /// Creates a renderer highlighting the code blocks with the theme found in
+    /// the themes directory.
+    pub(crate) fn new(themes: &Path) -> Result<Self> {
+        let theme_set = ThemeSet::load_from_folder(themes)
+            .with_context(|| format!("could not load the themes from {}", themes.display()))?;
+
+        // Note: the adapter would panic on the first code block otherwise.
+        if !theme_set.themes.contains_key(THEME_NAME) {
+            bail!("missing the {THEME_NAME:?} theme in {}", themes.display());
+        }
 
-    markdown_to_html_with_plugins(contents, &options, &plugins)
+        Ok(Self {
+            adapter: SyntectAdapterBuilder::new()
+                .theme_set(theme_set)
+                .theme(THEME_NAME)
+                .build(),
+            options: get_options(),
+        })
+    }
+
+    pub(crate) fn render(&self, contents: &str) -> String {
+        let mut plugins = ComrakPlugins::default();
+
+        plugins.render.codefence_syntax_highlighter = Some(&self.adapter);
+
+        markdown_to_html_with_plugins(contents, &self.options, &plugins)
+    }
+}
+
+/// Renders a snippet of markdown, e.g. a title, without the paragraph it
+/// would otherwise be wrapped in.
+pub(crate) fn inline_markdown_to_html(contents: &str) -> String {
+    let html = markdown_to_html(contents, &get_options());
+    let trimmed_html = html.trim_end();
+
+    match trimmed_html
+        .strip_prefix("<p>")
+        .and_then(|inner| inner.strip_suffix("</p>"))
+    {
+        // Note: several paragraphs are kept as is.
+        Some(inner) if !inner.contains("<p>") => inner.to_owned(),
+        _ => html,
+    }
 }
 
 pub(crate) fn get_markdown_file_name(filename: &str) -> Option<&str> {
@@ -42,3 +111,223 @@ pub(crate) fn get_markdown_file_name(filename: &str) -> Option<&str> {
 
     None
 }
+
+/// Gets the excerpt of a post.
+/// Uses the description if any, otherwise the contents before the more
+/// marker, otherwise the first paragraph truncated on a word boundary.
+pub(crate) fn get_excerpt(description: Option<&str>, contents: &str) -> Excerpt {
+    let options = get_options();
+
+    if let Some(source) = description.or_else(|| {
+        contents
+            .split_once(MORE_MARKER)
+            .map(|(before, _)| before.trim())
+    }) {
+        let arena = Arena::new();
+        let root = parse_document(&arena, source, &options);
+
+        return Excerpt {
+            html: markdown_to_html(source, &options),
+            text: get_plain_text(root),
+        };
+    }
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, contents, &options);
+
+    let Some(paragraph) = root
+        .descendants()
+        .find(|node| matches!(node.data.borrow().value, NodeValue::Paragraph))
+    else {
+        return Excerpt::default();
+    };
+
+    let text = get_plain_text(paragraph);
+
+    if let Some(truncated) = truncate_on_word_boundary(&text, EXCERPT_MAX_LENGTH) {
+        return Excerpt {
+            html: format!("<p>{}</p>\n", escape_html(&truncated)),
+            text: truncated,
+        };
+    }
+
+    let mut html = vec![];
+
+    // Note: writing to a vector can't fail.
+    format_html(paragraph, &options, &mut html).unwrap_or_default();
+
+    Excerpt {
+        html: String::from_utf8_lossy(&html).into_owned(),
+        text,
+    }
+}
+
+/// Collects the text of a node and its descendants.
+fn get_plain_text<'a>(node: &'a Node<'a, RefCell<Ast>>) -> String {
+    let mut text = String::new();
+
+    for descendant in node.descendants() {
+        match &descendant.data.borrow().value {
+            NodeValue::Text(literal) | NodeValue::Code(NodeCode { literal, .. }) => {
+                text.push_str(&String::from_utf8_lossy(literal));
+            }
+            NodeValue::LineBreak | NodeValue::SoftBreak => text.push(' '),
+            NodeValue::Paragraph | NodeValue::Heading(_) if !text.is_empty() => {
+                text.push(' ');
+            }
+            _ => (),
+        }
+    }
+
+    text.trim().to_owned()
+}
+
+/// Truncates the text on a word boundary.
+/// Returns `None` if the text is already short enough.
+fn truncate_on_word_boundary(text: &str, max_length: usize) -> Option<String> {
+    if text.chars().count() <= max_length {
+        return None;
+    }
+
+    let end = text
+        .char_indices()
+        .nth(max_length)
+        .map_or(text.len(), |(index, _)| index);
+    let truncated = &text[..end];
+    // Cut at the last whitespace unless the text is a single long word.
+    let truncated = truncated
+        .rfind(char::is_whitespace)
+        .map_or(truncated, |index| &truncated[..index]);
+
+    Some(format!(
+        "{}…",
+        truncated.trim_end_matches(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+    ))
+}
+
+/// Escapes the text to be safely embedded in HTML or XML.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets the text of some HTML, with its whitespace collapsed.
+    fn html_to_text(html: &str) -> String {
+        let mut text = String::new();
+        let mut in_tag = false;
+
+        for c in html.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                _ if !in_tag => text.push(c),
+                _ => (),
+            }
+        }
+
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&amp;", "&")
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn prefers_the_description() {
+        let excerpt = get_excerpt(
+            Some("From the *description*"),
+            "Before\n\n<!-- more -->\n\nAfter",
+        );
+
+        assert_eq!(excerpt.html, "<p>From the <em>description</em></p>\n");
+        assert_eq!(excerpt.text, "From the description");
+    }
+
+    #[test]
+    fn prefers_the_more_marker() {
+        let excerpt = get_excerpt(None, "First one.\n\nSecond *one*.\n\n<!-- more -->\n\nRest");
+
+        assert_eq!(
+            excerpt.html,
+            "<p>First one.</p>\n<p>Second <em>one</em>.</p>\n"
+        );
+        assert_eq!(excerpt.text, "First one. Second one.");
+
+        let excerpt = get_excerpt(None, "# Title\n\nFirst *one*.\n\nSecond one.");
+
+        assert_eq!(excerpt.html, "<p>First <em>one</em>.</p>\n");
+        assert_eq!(excerpt.text, "First one.");
+        assert_eq!(get_excerpt(None, "# Title").text, "");
+    }
+
+    #[test]
+    fn truncates_long_paragraphs() {
+        let text = format!("{}abcde", "word ".repeat(55));
+
+        assert_eq!(text.chars().count(), EXCERPT_MAX_LENGTH);
+        assert_eq!(truncate_on_word_boundary(&text, EXCERPT_MAX_LENGTH), None);
+        assert_eq!(get_excerpt(None, &text).text, text);
+
+        let excerpt = get_excerpt(None, &format!("{text}f"));
+
+        assert_eq!(excerpt.text, format!("{}…", "word ".repeat(55).trim_end()));
+        assert_eq!(excerpt.html, format!("<p>{}</p>\n", excerpt.text));
+        assert_eq!(
+            truncate_on_word_boundary("Hello, world", 8).unwrap(),
+            "Hello…"
+        );
+    }
+
+    #[test]
+    fn truncates_long_words() {
+        assert_eq!(
+            truncate_on_word_boundary(&"a".repeat(281), EXCERPT_MAX_LENGTH).unwrap(),
+            format!("{}…", "a".repeat(280))
+        );
+    }
+
+    #[test]
+    fn truncates_multibyte_text() {
+        assert_eq!(
+            truncate_on_word_boundary(&"é".repeat(280), EXCERPT_MAX_LENGTH),
+            None
+        );
+        assert_eq!(
+            truncate_on_word_boundary(&"é".repeat(281), EXCERPT_MAX_LENGTH).unwrap(),
+            format!("{}…", "é".repeat(280))
+        );
+        assert_eq!(
+            truncate_on_word_boundary(&"日本語 ".repeat(100), EXCERPT_MAX_LENGTH).unwrap(),
+            format!("{}…", "日本語 ".repeat(70).trim_end())
+        );
+    }
+
+    #[test]
+    fn keeps_html_and_text_consistent() {
+        for (description, contents) in [
+            (Some("A *b* & `<c>`"), ""),
+            (
+                None,
+                "A [link](/a) & **bold**\nover lines.\n\n<!-- more -->",
+            ),
+            (None, "# Title\n\nA ~~b~~ & `<c>` \"quoted\" 'text'."),
+            (None, &*format!("`<tag>` & {}", "word ".repeat(60))),
+            (None, &*format!("*{}* & `<tag>`", "word ".repeat(50))),
+        ] {
+            let excerpt = get_excerpt(description, contents);
+
+            assert!(!excerpt.text.is_empty(), "{contents:?}");
+            assert_eq!(html_to_text(&excerpt.html), excerpt.text, "{contents:?}");
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, fmt::Write};
+
+use time::OffsetDateTime;
+
+use crate::{
+    config::Config,
+    date::to_rfc3339,
+    feeds::{get_base_url, get_post_url},
+    file::encode_path_segment,
+    markdown::escape_html,
+    state::Post,
+    templates::{get_page_url, get_total_pages},
+};
+
+/// Renders the sitemap of the posts and the static pages.
+/// See the [protocol](https://www.sitemaps.org/protocol.html).
+/// Note: the about page is optional.
+pub(crate) fn render_sitemap(
+    config: &Config,
+    posts: &HashMap<String, Post>,
+    has_about: bool,
+) -> String {
+    let base_url = get_base_url(config);
+    let last_updated = posts.values().map(|post| post.updated).max();
+
+    // Tag pages change whenever one of their posts does.
+    let mut tags = HashMap::<&str, OffsetDateTime>::new();
+
+    for post in posts.values() {
+        for tag in &post.front_matter.tags {
+            let lastmod = tags.entry(tag).or_insert(post.updated);
+
+            *lastmod = (*lastmod).max(post.updated);
+        }
+    }
+
+    let mut urls = (1..=get_total_pages(posts.len(), config.listing.page_size))
+        .map(|page| (format!("{base_url}{}", get_page_url(page)), last_updated))
+        .chain(has_about.then(|| (format!("{base_url}/about"), None)))
+        .chain([(format!("{base_url}/tags"), last_updated)])
+        .chain(tags.into_iter().map(|(tag, lastmod)| {
+            (
+                format!("{base_url}/tags/{}", encode_path_segment(tag)),
+                Some(lastmod),
+            )
+        }))
+        .chain(
+            posts
+                .values()
+                .map(|post| (get_post_url(config, post), Some(post.updated))),
+        )
+        .collect::<Vec<(String, Option<OffsetDateTime>)>>();
+
+    // Keep the output stable across renders.
+    urls.sort();
+
+    let mut sitemap = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+    );
+
+    // Note: writing to a string can't fail.
+    for (url, lastmod) in urls {
+        let _ = write!(sitemap, "<url><loc>{}</loc>", escape_html(&url));
+
+        if let Some(lastmod) = lastmod {
+            let _ = write!(sitemap, "<lastmod>{}</lastmod>", to_rfc3339(lastmod));
+        }
+
+        sitemap.push_str("</url>");
+    }
+
+    sitemap.push_str("</urlset>");
+
+    sitemap
+}
+
+/// Renders the `robots.txt` file, pointing to the sitemap.
+pub(crate) fn render_robots(config: &Config) -> String {
+    let mut robots = String::from("User-agent: *\n");
+
+    for path in &config.robots.disallow {
+        let _ = writeln!(robots, "Disallow: {path}");
+    }
+
+    let _ = writeln!(robots, "\nSitemap: {}/sitemap.xml", get_base_url(config));
+
+    robots
+}
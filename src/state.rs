@@ -1,75 +1,267 @@
-use std::{collections::HashMap, fmt, sync::Arc, time::SystemTime};
+use std::{collections::HashMap, sync::Arc};
 
-use time::{format_description::well_known::Rfc2822, OffsetDateTime};
-use tokio::sync::Mutex;
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use time::OffsetDateTime;
+use tokio::{
+    sync::{watch, Mutex},
+    task::spawn_blocking,
+};
+
+use crate::{
+    cache::{compress, get_content_hash, CompressionLevel, Encoding},
+    config::Config,
+    date::DateSource,
+    error::BloggyError,
+    front_matter::FrontMatter,
+    markdown::Excerpt,
+    templates::RendererStatus,
+};
 
 #[derive(Debug)]
-pub(crate) struct MaybeSystemTime(Option<SystemTime>);
+pub(crate) struct Post {
+    /// The contents rendered to HTML, without the layout.
+    pub(crate) contents: String,
+    /// Where the publication date comes from, `None` if no source matched.
+    pub(crate) date_source: Option<DateSource>,
+    pub(crate) encoded_name: String,
+    pub(crate) excerpt: Excerpt,
+    pub(crate) front_matter: FrontMatter,
+    pub(crate) published: OffsetDateTime,
+    pub(crate) rendered_template: Page,
+    pub(crate) title: String,
+    pub(crate) updated: OffsetDateTime,
+}
+
+/// A rendered page along with its precompressed variants and the validators
+/// of the HTTP caches.
+/// Note: they are computed once, when the page is rendered.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Page {
+    pub(crate) body: Bytes,
+    pub(crate) brotli: Bytes,
+    pub(crate) gzip: Bytes,
+    /// Hash of the body.
+    pub(crate) hash: String,
+    pub(crate) last_modified: Option<OffsetDateTime>,
+    pub(crate) zstd: Bytes,
+}
+
+impl Page {
+    /// Creates a page, compressing it with every encoding.
+    /// Note: compressing is CPU bound, see [`Page::create`].
+    pub(crate) fn new(
+        body: impl Into<Bytes>,
+        last_modified: Option<OffsetDateTime>,
+        level: CompressionLevel,
+    ) -> Result<Self> {
+        let body = body.into();
+        let hash = get_content_hash(&body);
+
+        Self::compress(body, hash, last_modified, level)
+    }
+
+    /// Creates the new version of a page, last modified now unless its body is
+    /// the same as the one of the previous version, which is then reused as
+    /// is.
+    /// Note: this suits the pages listing the posts, as the dates of the posts
+    /// don't tell when one is removed or when the templates are reloaded.
+    pub(crate) fn new_version(
+        body: impl Into<Bytes>,
+        previous: Option<&Page>,
+        level: CompressionLevel,
+    ) -> Result<Self> {
+        let body = body.into();
+        let hash = get_content_hash(&body);
+
+        match previous {
+            Some(previous) if previous.hash == hash => Ok(previous.clone()),
+            _ => Self::compress(body, hash, Some(OffsetDateTime::now_utc()), level),
+        }
+    }
+
+    /// Moves the last modification of a page to now if its body changed
+    /// compared to the previous version, keeping the later of both dates.
+    /// Note: a post can be edited, or its template reloaded, without bumping
+    /// its `updated` date.
+    pub(crate) fn changed_since(mut self, previous: Option<&Page>) -> Self {
+        let changed = match previous {
+            Some(previous) if previous.hash == self.hash => previous.last_modified,
+            _ => Some(OffsetDateTime::now_utc()),
+        };
+
+        self.last_modified = self.last_modified.max(changed);
+
+        self
+    }
 
-impl MaybeSystemTime {
-    pub(crate) fn new(maybe_system_time: Option<SystemTime>) -> Self {
-        Self(maybe_system_time)
+    /// Creates a page on the blocking threads.
+    pub(crate) async fn create(
+        body: impl Into<Bytes> + Send + 'static,
+        last_modified: Option<OffsetDateTime>,
+        level: CompressionLevel,
+    ) -> Result<Self> {
+        spawn_blocking(move || Self::new(body, last_modified, level)).await?
     }
 
-    pub(crate) fn get(&self) -> String {
-        match self.0 {
-            Some(system_time) => {
-                let offset_date_time: OffsetDateTime = system_time.into();
+    /// Compresses the variants of a body whose hash is already known.
+    fn compress(
+        body: Bytes,
+        hash: String,
+        last_modified: Option<OffsetDateTime>,
+        level: CompressionLevel,
+    ) -> Result<Self> {
+        Ok(Self {
+            brotli: Bytes::from(compress(Encoding::Brotli, level, &body)?),
+            gzip: Bytes::from(compress(Encoding::Gzip, level, &body)?),
+            hash,
+            last_modified,
+            zstd: Bytes::from(compress(Encoding::Zstd, level, &body)?),
+            body,
+        })
+    }
 
-                offset_date_time.format(&Rfc2822).unwrap()
-            }
-            None => "No date".to_owned(),
+    /// Gets the strong entity tag of a variant, `None` being the body itself.
+    pub(crate) fn get_etag(&self, encoding: Option<Encoding>) -> String {
+        match encoding {
+            Some(encoding) => format!("\"{}-{}\"", self.hash, encoding.name()),
+            None => format!("\"{}\"", self.hash),
+        }
+    }
+
+    /// Gets a variant, `None` being the body itself.
+    pub(crate) fn get_variant(&self, encoding: Option<Encoding>) -> Bytes {
+        match encoding {
+            Some(Encoding::Brotli) => self.brotli.clone(),
+            Some(Encoding::Gzip) => self.gzip.clone(),
+            Some(Encoding::Zstd) => self.zstd.clone(),
+            None => self.body.clone(),
         }
     }
 }
 
-impl fmt::Display for MaybeSystemTime {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.get())
+/// Pages rendered from the whole set of posts.
+/// They are all refreshed together whenever a post changes.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DerivedPages {
+    /// Atom 1.0 feed of the posts.
+    pub(crate) atom: Page,
+    /// JSON Feed 1.1 feed of the posts.
+    pub(crate) json_feed: Page,
+    /// The `robots.txt` file.
+    pub(crate) robots: Page,
+    /// Pages of the root listing, the first one being served at `/`.
+    pub(crate) root_pages: Vec<Page>,
+    /// RSS 2.0 feed of the posts.
+    pub(crate) rss: Page,
+    pub(crate) sitemap: Page,
+    pub(crate) tag_index: Page,
+    /// Atom 1.0 feeds keyed by tag name.
+    pub(crate) tag_atoms: HashMap<String, Page>,
+    /// Tag pages keyed by tag name.
+    pub(crate) tags: HashMap<String, Page>,
+}
+
+/// Pages served along with the error statuses.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ErrorPages {
+    /// Served with `500 Internal Server Error`.
+    pub(crate) internal_error: Page,
+    pub(crate) not_found: Page,
+    /// Served with `503 Service Unavailable`, while the renderer restarts.
+    pub(crate) unavailable: Page,
+}
+
+impl ErrorPages {
+    /// Gets the page of an error status, if any.
+    pub(crate) fn get(&self, status: StatusCode) -> Option<&Page> {
+        match status {
+            StatusCode::INTERNAL_SERVER_ERROR => Some(&self.internal_error),
+            StatusCode::NOT_FOUND => Some(&self.not_found),
+            StatusCode::SERVICE_UNAVAILABLE => Some(&self.unavailable),
+            _ => None,
+        }
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct Post {
-    pub(crate) created: MaybeSystemTime,
-    pub(crate) encoded_name: String,
-    pub(crate) rendered_template: String,
+/// Snapshot of the rendered site, served as is by the handlers.
+/// Note: a snapshot is never mutated, the watchers build a new one and swap
+/// it in, so that reads never wait for writes.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Site {
+    /// The about page, empty if there is none.
+    pub(crate) about: Page,
+    pub(crate) derived_pages: DerivedPages,
+    pub(crate) error_pages: ErrorPages,
+    /// Rendered posts keyed by name.
+    pub(crate) posts: HashMap<String, Page>,
 }
 
-impl Post {
+impl Site {
     pub(crate) fn new(
-        created: MaybeSystemTime,
-        encoded_name: String,
-        rendered_template: String,
+        about: Page,
+        derived_pages: DerivedPages,
+        error_pages: ErrorPages,
+        posts: &HashMap<String, Post>,
     ) -> Self {
         Self {
-            created,
-            encoded_name,
-            rendered_template,
+            about,
+            derived_pages,
+            error_pages,
+            // Note: cloning a page only bumps the reference count of its body.
+            posts: posts
+                .iter()
+                .map(|(name, post)| (name.clone(), post.rendered_template.clone()))
+                .collect(),
+        }
+    }
+
+    /// Gets the page of a post, the about page included.
+    pub(crate) fn get_post_page(&self, original_name: &str) -> Option<&Page> {
+        if original_name == "about" {
+            Some(&self.about)
+        } else {
+            self.posts.get(original_name)
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct AppState {
-    pub(crate) about_template: Arc<Mutex<String>>,
-    pub(crate) not_found_template: Arc<Mutex<String>>,
+    pub(crate) config: Arc<Config>,
+    /// The posts, as the watchers publish them.
     pub(crate) posts: Arc<Mutex<HashMap<String, Post>>>,
-    pub(crate) root_template: Arc<Mutex<String>>,
+    /// Held while a watcher publishes, so that a change set of the posts and
+    /// a reload of the templates never interleave.
+    pub(crate) publishing: Arc<Mutex<()>>,
+    pub(crate) renderer_status: watch::Receiver<RendererStatus>,
+    pub(crate) site: Arc<ArcSwap<Site>>,
 }
 
 impl AppState {
     pub(crate) fn new(
-        about_template: Arc<Mutex<String>>,
-        not_found_template: Arc<Mutex<String>>,
+        config: Arc<Config>,
         posts: Arc<Mutex<HashMap<String, Post>>>,
-        root_template: Arc<Mutex<String>>,
+        renderer_status: watch::Receiver<RendererStatus>,
+        site: Arc<ArcSwap<Site>>,
     ) -> Self {
         Self {
-            about_template,
-            not_found_template,
+            config,
             posts,
-            root_template,
+            publishing: Arc::new(Mutex::new(())),
+            renderer_status,
+            site,
+        }
+    }
+
+    /// Fails while the renderer restarts, as the changes to the posts
+    /// couldn't be published.
+    pub(crate) fn ensure_renderer_running(&self) -> Result<(), BloggyError> {
+        match *self.renderer_status.borrow() {
+            RendererStatus::Running => Ok(()),
+            RendererStatus::Restarting => Err(BloggyError::Unavailable),
         }
     }
 }
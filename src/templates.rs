@@ -1,141 +1,500 @@
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 
-use anyhow::Result;
-use minijinja::{context, Environment};
+use anyhow::{anyhow, Context, Result};
+use axum::http::StatusCode;
+use minijinja::{context, value::Value, Environment, ErrorKind, Source};
 use serde::Serialize;
+use time::OffsetDateTime;
 use tokio::{
-    fs::{create_dir_all, read_dir, read_to_string},
-    sync::{mpsc, oneshot, Mutex},
+    fs::{create_dir_all, metadata, read, read_dir},
+    sync::{mpsc, oneshot, watch, Mutex, Semaphore},
+    task::{spawn_blocking, JoinHandle, JoinSet},
+    time::sleep,
 };
 
 use crate::{
-    file::get_file_descriptor_from_paths, file::FileDescriptor, markdown::contents_to_markdown,
-    state::MaybeSystemTime, Post,
+    cache::CompressionLevel,
+    config::{Config, SortDirection},
+    date::{parse_date_prefix, to_rfc2822, to_rfc3339, DateCandidates, DateSource, DateSources},
+    feeds::{get_feed_links, render_atom, render_json_feed, render_rss},
+    file::{encode_path_segment, get_file_descriptor_from_paths, is_post_file, FileDescriptor},
+    filters::add_filters,
+    front_matter::{parse_front_matter, FrontMatter},
+    markdown::{get_excerpt, Excerpt, MarkdownRenderer},
+    sitemap::{render_robots, render_sitemap},
+    state::{DerivedPages, ErrorPages, Page, Site},
+    Post,
 };
 
 #[derive(Debug)]
 pub(crate) struct PostTemplate {
+    /// The contents, already rendered to HTML.
     pub(crate) contents: String,
+    pub(crate) front_matter: FrontMatter,
+    /// Name of the template to render the post with.
+    pub(crate) template: String,
     pub(crate) title: String,
 }
 
 #[derive(Debug)]
 pub(crate) enum TemplateKind {
+    /// Renders `error.html` for an error status.
+    Error(StatusCode),
+    /// Renders markdown to HTML, without any template.
+    Markdown(String),
+    /// Renders `404.html`.
     NotFound,
-    Post(PostTemplate),
-    Root,
+    /// Renders a post or a page with the template it selects.
+    Post(Box<PostTemplate>),
+    /// Renders a page of the root listing with `list.html`, starting at 1.
+    Root(usize),
+    /// Renders the posts of a tag with `tag.html`.
+    Tag(String),
+    /// Renders the index of the tags with `tags.html`.
+    Tags,
 }
 
 #[derive(Debug, Serialize)]
 struct PreviewPost {
     date: String,
-    description: String,
+    date_source: Option<DateSource>,
     encoded_name: String,
+    excerpt: Excerpt,
+    front_matter: FrontMatter,
     original_name: String,
+    published: String,
+    tags: Vec<TagLink>,
+    title: String,
+    updated: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TagLink {
+    encoded_name: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TagSummary {
+    count: usize,
+    encoded_name: String,
+    name: String,
 }
 
 #[derive(Debug)]
 pub(crate) struct InitialTemplates {
-    pub(crate) about_template: String,
-    pub(crate) not_found_template: String,
-    pub(crate) root_template: String,
+    /// The about page, empty if there is none.
+    pub(crate) about_template: Page,
+    pub(crate) derived_pages: DerivedPages,
+    pub(crate) error_pages: ErrorPages,
+    /// Posts which could not be loaded, with the reason.
+    pub(crate) failed_posts: Vec<(String, anyhow::Error)>,
 }
 
-pub(crate) async fn templates_manager(
-    posts: Arc<Mutex<HashMap<String, Post>>>,
-) -> Result<mpsc::Sender<(TemplateKind, oneshot::Sender<String>)>> {
-    let (sender, mut rx) = mpsc::channel::<(TemplateKind, oneshot::Sender<String>)>(1);
+/// Templates used when the templates directory doesn't provide them.
+const BUILT_IN_TEMPLATES: &[(&str, &str)] = &[
+    ("404.html", include_str!("../public/404.html")),
+    ("base.html", include_str!("../public/base.html")),
+    ("error.html", include_str!("../public/error.html")),
+    ("list.html", include_str!("../public/list.html")),
+    ("page.html", include_str!("../public/page.html")),
+    ("post.html", include_str!("../public/post.html")),
+    ("preview.html", include_str!("../public/preview.html")),
+    ("tag.html", include_str!("../public/tag.html")),
+    ("tags.html", include_str!("../public/tags.html")),
+];
+
+/// Template of the posts, unless their front matter says otherwise.
+const POST_TEMPLATE: &str = "post.html";
+
+/// Template of the standalone pages, e.g. the about page.
+const PAGE_TEMPLATE: &str = "page.html";
+
+/// How long the renderer waits before restarting after a failure.
+pub(crate) const RENDERER_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// How long the renderer may leave the pending renders unanswered before
+/// being restarted.
+const RENDERER_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A render to run, along with where to send the result.
+pub(crate) type RenderRequest = (TemplateKind, oneshot::Sender<Result<String>>);
+
+/// State of the task dispatching the renders.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum RendererStatus {
+    Running,
+    /// The task failed or stalled and is about to be spawned again.
+    Restarting,
+}
+
+/// Activity of a dispatch task, telling a busy one from a stalled one.
+#[derive(Debug, Default)]
+struct RenderActivity {
+    /// Renders answered, successfully or not.
+    answered: AtomicUsize,
+    /// Renders received and not answered yet.
+    pending: AtomicUsize,
+}
+
+impl RenderActivity {
+    /// Waits until renders are pending but none of them was answered for the
+    /// stall timeout.
+    async fn stalled(&self) {
+        let mut last_answered = self.answered.load(Ordering::Relaxed);
+
+        loop {
+            sleep(RENDERER_STALL_TIMEOUT).await;
+
+            let answered = self.answered.load(Ordering::Relaxed);
+
+            if answered == last_answered && self.pending.load(Ordering::Relaxed) > 0 {
+                return;
+            }
 
-    // Prepare the environment and add the main template.
+            last_answered = answered;
+        }
+    }
+}
+
+/// Renders the templates and the markdown of the posts.
+#[derive(Debug)]
+pub(crate) struct Renderer {
+    pub(crate) environment: Environment<'static>,
+    pub(crate) markdown: MarkdownRenderer,
+}
+
+impl Renderer {
+    /// Loads the templates and the syntax highlighting themes.
+    pub(crate) fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            environment: create_environment(config)?,
+            markdown: MarkdownRenderer::new(&config.paths.themes)?,
+        })
+    }
+}
+
+/// Creates an environment loading the templates from the templates
+/// directory, falling back to the built-in ones.
+/// Note: every template is compiled upfront to report errors early.
+fn create_environment(config: &Config) -> Result<Environment<'static>> {
+    let templates_dir = config.paths.templates.clone();
     let mut env = Environment::new();
-    env.add_template("index", include_str!("../public/index.html"))
-        .unwrap();
+
+    env.set_source(Source::with_loader(move |name| {
+        load_template(&templates_dir, name)
+    }));
+    add_filters(&mut env, config);
+
+    for (name, _) in BUILT_IN_TEMPLATES {
+        env.get_template(name)?;
+    }
+
+    Ok(env)
+}
+
+/// Loads a template from the file of the same name.
+fn load_template(templates_dir: &Path, name: &str) -> Result<Option<String>, minijinja::Error> {
+    // Note: names must not escape the templates directory.
+    if name.starts_with('.') || name.contains(['/', '\\']) {
+        return Ok(None);
+    }
+
+    match std::fs::read_to_string(templates_dir.join(name)) {
+        Ok(template) => Ok(Some(template)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(BUILT_IN_TEMPLATES
+            .iter()
+            .find(|(built_in_name, _)| *built_in_name == name)
+            .map(|(_, template)| (*template).to_owned())),
+        Err(error) => Err(minijinja::Error::new(
+            ErrorKind::InvalidOperation,
+            "could not read template",
+        )
+        .with_source(error)),
+    }
+}
+
+/// Spawns the task dispatching the renders to a pool of workers, under a
+/// supervisor spawning it again whenever it fails or stalls, e.g. on a render
+/// which never ends.
+/// Returns the sender to request renders, the one to swap the renderer, e.g.
+/// when the templates or the themes change, and the status of the task.
+/// Note: requests wait in a bounded queue while every worker is busy, which
+/// applies backpressure to the requesters. The queue outlives the task, so
+/// that the requests survive a restart.
+pub(crate) async fn templates_manager(
+    config: Arc<Config>,
+    posts: Arc<Mutex<HashMap<String, Post>>>,
+) -> Result<(
+    mpsc::Sender<RenderRequest>,
+    watch::Sender<Arc<Renderer>>,
+    watch::Receiver<RendererStatus>,
+)> {
+    let (sender, rx) = mpsc::channel::<RenderRequest>(config.renderer.queue_size);
+    let (renderer_sender, renderer) = watch::channel(Arc::new(Renderer::new(&config)?));
+    let (status_sender, status) = watch::channel(RendererStatus::Running);
+    let rx = Arc::new(Mutex::new(rx));
 
     tokio::spawn(async move {
-        let template = env.get_template("index").unwrap();
-
-        while let Some((template_kind, response)) = rx.recv().await {
-            match template_kind {
-                TemplateKind::NotFound => {
-                    let rendered_template = template
-                        .render(context!(
-                            contents => contents_to_markdown("# 404\nPage not found."),
-                            is_root => false,
-                            public => "/public/",
-                            title => "404",
-                        ))
-                        .unwrap();
-
-                    response.send(rendered_template).unwrap();
-                }
-                TemplateKind::Post(PostTemplate { contents, title }) => {
-                    let rendered_template = template
-                        .render(context!(
-                            contents => contents_to_markdown(&contents),
-                            is_root => false,
-                            public => "/public/",
-                            title,
-                        ))
-                        .unwrap();
-
-                    response.send(rendered_template).unwrap();
-                }
-                TemplateKind::Root => {
-                    let posts = posts.lock().await;
-                    let mut preview_posts = posts
-                        .iter()
-                        // Filter out the `about` page.
-                        // Note: we can rely on the encoded name here.
-                        .filter(|(name, _)| *name != "about")
-                        .map(|(original_name, post)| PreviewPost {
-                            date: post.created.get(),
-                            description: "todo".to_owned(),
-                            encoded_name: post.encoded_name.clone(),
-                            original_name: original_name.clone(),
-                        })
-                        .collect::<Vec<PreviewPost>>();
-
-                    // TODO: this is just not to forget and might not work!
-                    preview_posts.sort_by(|a, b| a.date.cmp(&b.date));
-
-                    let rendered_template = template
-                        .render(context!(
-                            is_root => true,
-                            posts => preview_posts,
-                            public => "/public/",
-                            title => "Home",
-                        ))
-                        .unwrap();
-
-                    response.send(rendered_template).unwrap();
+        loop {
+            let activity = Arc::new(RenderActivity::default());
+            // Note: the renders can't be cancelled, a stalled one keeps its
+            // worker, hence a new pool of workers.
+            let workers = Arc::new(Semaphore::new(config.renderer.get_workers()));
+            let mut dispatcher = tokio::spawn(dispatch_renders(
+                Arc::clone(&rx),
+                Arc::clone(&config),
+                Arc::clone(&posts),
+                renderer.clone(),
+                workers,
+                Arc::clone(&activity),
+            ));
+
+            tokio::select! {
+                result = &mut dispatcher => match result {
+                    // Note: the task only returns once every sender is dropped.
+                    Ok(()) => break,
+                    Err(error) => tracing::error!("the renderer failed, restarting it: {error}"),
+                },
+                () = activity.stalled() => {
+                    // Note: the pending renders are dropped, which their
+                    // requesters get as an error.
+                    dispatcher.abort();
+
+                    tracing::error!("the renderer stalled, restarting it");
                 }
             }
-        }
 
-        Ok::<_, anyhow::Error>(())
+            status_sender.send_replace(RendererStatus::Restarting);
+            sleep(RENDERER_RESTART_DELAY).await;
+            status_sender.send_replace(RendererStatus::Running);
+        }
     });
 
-    Ok(sender)
+    Ok((sender, renderer_sender, status))
+}
+
+/// Receives the render requests, shared by the successive dispatch tasks.
+type RenderReceiver = Arc<Mutex<mpsc::Receiver<RenderRequest>>>;
+
+/// Dispatches the renders to the workers until every sender is dropped.
+/// Note: the renders are owned by the task, so that aborting it drops their
+/// requests too.
+async fn dispatch_renders(
+    rx: RenderReceiver,
+    config: Arc<Config>,
+    posts: Arc<Mutex<HashMap<String, Post>>>,
+    renderer: watch::Receiver<Arc<Renderer>>,
+    workers: Arc<Semaphore>,
+    activity: Arc<RenderActivity>,
+) {
+    let mut rx = rx.lock().await;
+    let mut renders = JoinSet::new();
+
+    loop {
+        let (template_kind, response) = tokio::select! {
+            // Note: the finished renders are reaped along the way.
+            Some(_) = renders.join_next() => continue,
+            request = rx.recv() => match request {
+                Some(request) => request,
+                None => break,
+            },
+        };
+
+        activity.pending.fetch_add(1, Ordering::Relaxed);
+
+        // Note: the context is gathered before waiting for a worker, so that
+        // the workers only cover CPU bound work, never the posts lock.
+        let render = prepare_render(&config, &posts, template_kind).await;
+        // Note: the semaphore is never closed.
+        let Ok(permit) = Arc::clone(&workers).acquire_owned().await else {
+            break;
+        };
+        let renderer = Arc::clone(&renderer.borrow());
+        let activity = Arc::clone(&activity);
+
+        renders.spawn(async move {
+            // Note: markdown and templates are rendered on the blocking
+            // threads as they are CPU bound.
+            let rendered_template = spawn_blocking(move || render.run(&renderer)).await;
+
+            drop(permit);
+
+            // Note: the requester may be gone, e.g. after a restart.
+            let _ = response.send(match rendered_template {
+                Ok(rendered_template) => rendered_template.context("failed to render a template"),
+                Err(error) => Err(anyhow!("a render worker failed: {error}")),
+            });
+
+            activity.pending.fetch_sub(1, Ordering::Relaxed);
+            activity.answered.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    while renders.join_next().await.is_some() {}
 }
 
+/// A render with everything it needs, ready to run on a worker.
+enum Render {
+    Markdown(String),
+    Template { context: Value, name: String },
+}
+
+impl Render {
+    fn run(self, renderer: &Renderer) -> Result<String, minijinja::Error> {
+        match self {
+            Render::Markdown(contents) => Ok(renderer.markdown.render(&contents)),
+            Render::Template { context, name } => {
+                renderer.environment.get_template(&name)?.render(context)
+            }
+        }
+    }
+}
+
+/// Gathers the context of a render.
+/// Note: the posts are locked here rather than on the workers.
+async fn prepare_render(
+    config: &Config,
+    posts: &Mutex<HashMap<String, Post>>,
+    template_kind: TemplateKind,
+) -> Render {
+    let (name, context) = match template_kind {
+        TemplateKind::Error(status) => (
+            "error.html".to_owned(),
+            context!(
+                config,
+                feeds => get_feed_links(config, None),
+                public => "/public/",
+                reason => status.canonical_reason(),
+                status => status.as_u16(),
+                title => status.as_str(),
+            ),
+        ),
+        TemplateKind::Markdown(contents) => return Render::Markdown(contents),
+        TemplateKind::NotFound => (
+            "404.html".to_owned(),
+            context!(
+                config,
+                feeds => get_feed_links(config, None),
+                public => "/public/",
+                title => "404",
+            ),
+        ),
+        TemplateKind::Post(post_template) => {
+            let PostTemplate {
+                contents,
+                front_matter,
+                template,
+                title,
+            } = *post_template;
+
+            (
+                template,
+                context!(
+                    config,
+                    contents,
+                    feeds => get_feed_links(config, None),
+                    front_matter,
+                    public => "/public/",
+                    title,
+                ),
+            )
+        }
+        TemplateKind::Root(current_page) => (
+            "list.html".to_owned(),
+            get_root_context(config, &*posts.lock().await, current_page),
+        ),
+        TemplateKind::Tag(tag) => {
+            let posts = posts.lock().await;
+            let preview_posts = get_preview_posts(
+                posts
+                    .iter()
+                    .filter(|(_, post)| post.front_matter.tags.contains(&tag))
+                    .collect(),
+                config.listing.sort,
+            );
+
+            (
+                "tag.html".to_owned(),
+                context!(
+                    config,
+                    feeds => get_feed_links(config, Some(&tag)),
+                    posts => preview_posts,
+                    public => "/public/",
+                    tag => tag,
+                    title => format!("Tag: {tag}"),
+                ),
+            )
+        }
+        TemplateKind::Tags => {
+            let tags = get_tags(&*posts.lock().await)
+                .into_iter()
+                .map(|(name, count)| TagSummary {
+                    count,
+                    encoded_name: encode_path_segment(&name),
+                    name,
+                })
+                .collect::<Vec<TagSummary>>();
+
+            (
+                "tags.html".to_owned(),
+                context!(
+                    config,
+                    feeds => get_feed_links(config, None),
+                    public => "/public/",
+                    tags,
+                    title => "Tags",
+                ),
+            )
+        }
+    };
+
+    Render::Template { context, name }
+}
+
+/// Loads every post then renders the pages of the site.
+/// The pages are compared to the previous ones, if any, see
+/// [`render_derived_pages`] and [`Page::changed_since`].
+/// Note: the pages are compressed as much as possible on startup, e.g. for an
+/// export, and faster when rendered again while serving.
 pub(crate) async fn generate_initial_templates(
+    config: &Config,
     posts: Arc<Mutex<HashMap<String, Post>>>,
-    sender: mpsc::Sender<(TemplateKind, oneshot::Sender<String>)>,
+    sender: mpsc::Sender<RenderRequest>,
+    previous: Option<&Site>,
 ) -> Result<InitialTemplates> {
     // Ensure that the directory exists upfront.
     // Note: if the directory already exists, it will be a noop and no error
     // will be returned.
-    create_dir_all("./posts").await?;
+    create_dir_all(&config.paths.posts).await?;
 
-    let mut posts_stream = read_dir("./posts").await?;
+    let level = if previous.is_some() {
+        CompressionLevel::Fast
+    } else {
+        CompressionLevel::Best
+    };
 
-    let mut posts = posts.lock().await;
+    let mut posts_stream = read_dir(&config.paths.posts).await?;
 
-    let mut about_template = String::new();
+    let mut loaded_posts = HashMap::new();
+    let mut about_template = Page::default();
+    let mut failed_posts = Vec::new();
+    let mut loading_posts = JoinSet::new();
 
     while let Some(dir_entry) = posts_stream.next_entry().await? {
-        let metadata = dir_entry.metadata().await?;
-        let created = MaybeSystemTime::new(metadata.modified().ok());
+        if !is_post_file(&dir_entry.path()) {
+            continue;
+        }
+
+        let path = dir_entry.path();
         let file_name = dir_entry.file_name();
 
         let (encoded_name, original_name) =
@@ -148,53 +507,448 @@ pub(crate) async fn generate_initial_templates(
                  }| (encoded_name, original_name),
             );
 
-        let contents = read_to_string(dir_entry.path()).await?;
+        let date_sources = config.dates.sources.clone();
+        let sender = sender.clone();
+        let previous_page = previous
+            .and_then(|previous| previous.get_post_page(&original_name))
+            .cloned();
+
+        // Note: the posts are loaded concurrently so that the workers render
+        // them in parallel.
+        // Note: a post which can't be read is reported like an invalid one.
+        loading_posts.spawn(async move {
+            let post = async {
+                let (contents, file_time) = read_post_file(&path).await?;
+                let mut post = load_post(
+                    &sender,
+                    &date_sources,
+                    &contents,
+                    encoded_name,
+                    &original_name,
+                    file_time,
+                    level,
+                )
+                .await?;
 
-        let rendered_template = get_rendered_template(
-            &sender,
-            TemplateKind::Post(PostTemplate {
-                contents,
-                title: original_name.clone(),
-            }),
-        )
-        .await?;
+                post.rendered_template =
+                    post.rendered_template.changed_since(previous_page.as_ref());
+
+                Ok(post)
+            }
+            .await;
+
+            (original_name, post)
+        });
+    }
+
+    while let Some(loaded_post) = loading_posts.join_next().await {
+        let (original_name, post) = loaded_post?;
+
+        let post = match post {
+            Ok(post) => post,
+            Err(error) => {
+                tracing::warn!("skipping {original_name:?}: {error:#}");
+
+                failed_posts.push((original_name, error));
+
+                continue;
+            }
+        };
 
         if original_name == "about" {
-            about_template = rendered_template;
+            about_template = post.rendered_template;
         } else {
-            posts.insert(
-                original_name,
-                Post {
-                    created,
-                    encoded_name,
-                    rendered_template,
-                },
-            );
+            loaded_posts.insert(original_name, post);
         }
     }
 
-    // Unlock the mutex to avoid a deadlock.
-    drop(posts);
-
-    let not_found_template = get_rendered_template(&sender, TemplateKind::NotFound).await?;
-    let root_template = get_rendered_template(&sender, TemplateKind::Root).await?;
+    // Note: the posts finish loading in any order.
+    failed_posts.sort_by(|(a_name, _), (b_name, _)| a_name.cmp(b_name));
+
+    // Note: the posts are swapped in once the whole site is rendered, which
+    // drops the stale ones on reload and keeps the current ones on failure.
+    let loaded_posts = Mutex::new(loaded_posts);
+    let error_pages = render_error_pages(&sender, level).await?;
+    let derived_pages = render_derived_pages(
+        config,
+        &loaded_posts,
+        &sender,
+        previous.map(|previous| &previous.derived_pages),
+        !about_template.body.is_empty(),
+        level,
+    )
+    .await?;
+
+    *posts.lock().await = loaded_posts.into_inner();
 
     Ok(InitialTemplates {
         about_template,
-        not_found_template,
-        root_template,
+        derived_pages,
+        error_pages,
+        failed_posts,
+    })
+}
+
+/// Renders the pages served along with the error statuses.
+async fn render_error_pages(
+    sender: &mpsc::Sender<RenderRequest>,
+    level: CompressionLevel,
+) -> Result<ErrorPages> {
+    let rendered_templates = get_rendered_templates(
+        sender,
+        [
+            TemplateKind::Error(StatusCode::INTERNAL_SERVER_ERROR),
+            TemplateKind::NotFound,
+            TemplateKind::Error(StatusCode::SERVICE_UNAVAILABLE),
+        ]
+        .into_iter(),
+    )
+    .await?;
+    // Note: there is one rendered template per request.
+    let [internal_error, not_found, unavailable] =
+        <[String; 3]>::try_from(rendered_templates).map_err(|_| anyhow!("missing error pages"))?;
+
+    Ok(ErrorPages {
+        internal_error: Page::create(internal_error, None, level).await?,
+        not_found: Page::create(not_found, None, level).await?,
+        unavailable: Page::create(unavailable, None, level).await?,
+    })
+}
+
+/// Renders the pages derived from the posts, e.g. the root listing or the
+/// tag pages.
+/// Note: the pages are last modified when their body changes compared to the
+/// previous ones, if any.
+pub(crate) async fn render_derived_pages(
+    config: &Config,
+    posts: &Mutex<HashMap<String, Post>>,
+    sender: &mpsc::Sender<RenderRequest>,
+    previous: Option<&DerivedPages>,
+    has_about: bool,
+    level: CompressionLevel,
+) -> Result<DerivedPages> {
+    // Note: the lock is released before rendering to avoid a deadlock.
+    let (post_count, rss, atom, json_feed, sitemap, tag_names, tag_atoms) = {
+        let posts = posts.lock().await;
+        let tag_names = get_tags(&posts).into_keys().collect::<Vec<String>>();
+        let tag_atoms = tag_names
+            .iter()
+            .map(|tag| (tag.clone(), render_atom(config, &posts, Some(tag))))
+            .collect::<Vec<(String, String)>>();
+
+        (
+            posts.len(),
+            render_rss(config, &posts),
+            render_atom(config, &posts, None),
+            render_json_feed(config, &posts),
+            render_sitemap(config, &posts, has_about),
+            tag_names,
+            tag_atoms,
+        )
+    };
+
+    let total_pages = get_total_pages(post_count, config.listing.page_size);
+    let root_pages =
+        get_rendered_templates(sender, (1..=total_pages).map(TemplateKind::Root)).await?;
+    let tag_index = get_rendered_template(sender, TemplateKind::Tags).await?;
+    let tag_pages =
+        get_rendered_templates(sender, tag_names.iter().cloned().map(TemplateKind::Tag)).await?;
+
+    // Note: the pages are compressed in parallel on the blocking threads.
+    let create_page = |body: String, previous: Option<&Page>| {
+        let previous = previous.cloned();
+
+        spawn_blocking(move || Page::new_version(body, previous.as_ref(), level))
+    };
+    let atom = create_page(atom, previous.map(|previous| &previous.atom));
+    let json_feed = create_page(json_feed, previous.map(|previous| &previous.json_feed));
+    let robots = create_page(
+        render_robots(config),
+        previous.map(|previous| &previous.robots),
+    );
+    let rss = create_page(rss, previous.map(|previous| &previous.rss));
+    let sitemap = create_page(sitemap, previous.map(|previous| &previous.sitemap));
+    let tag_index = create_page(tag_index, previous.map(|previous| &previous.tag_index));
+    let root_pages = root_pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, root_page)| {
+            let previous = previous.and_then(|previous| previous.root_pages.get(index));
+
+            (index, create_page(root_page, previous))
+        })
+        .collect();
+    let tag_pages = tag_names
+        .into_iter()
+        .zip(tag_pages)
+        .map(|(tag, tag_page)| {
+            let previous = previous.and_then(|previous| previous.tags.get(&tag));
+
+            (tag, create_page(tag_page, previous))
+        })
+        .collect();
+    let tag_atoms = tag_atoms
+        .into_iter()
+        .map(|(tag, tag_atom)| {
+            let previous = previous.and_then(|previous| previous.tag_atoms.get(&tag));
+
+            (tag, create_page(tag_atom, previous))
+        })
+        .collect();
+
+    Ok(DerivedPages {
+        atom: atom.await??,
+        json_feed: json_feed.await??,
+        robots: robots.await??,
+        root_pages: join_pages(root_pages)
+            .await?
+            .into_iter()
+            .map(|(_, root_page)| root_page)
+            .collect(),
+        rss: rss.await??,
+        sitemap: sitemap.await??,
+        tag_atoms: join_pages(tag_atoms).await?.into_iter().collect(),
+        tag_index: tag_index.await??,
+        tags: join_pages(tag_pages).await?.into_iter().collect(),
     })
 }
 
+/// Waits for the pages created on the blocking threads, keeping their keys.
+async fn join_pages<K>(pages: Vec<(K, JoinHandle<Result<Page>>)>) -> Result<Vec<(K, Page)>> {
+    let mut joined_pages = Vec::with_capacity(pages.len());
+
+    for (key, page) in pages {
+        joined_pages.push((key, page.await??));
+    }
+
+    Ok(joined_pages)
+}
+
+/// Gets the context of a page of the root listing.
+fn get_root_context(config: &Config, posts: &HashMap<String, Post>, current_page: usize) -> Value {
+    let page_size = config.listing.page_size;
+    let preview_posts = get_preview_posts(
+        posts
+            .iter()
+            // Filter out the `about` page.
+            // Note: we can rely on the encoded name here.
+            .filter(|(name, _)| *name != "about")
+            .collect(),
+        config.listing.sort,
+    );
+    let total_pages = get_total_pages(preview_posts.len(), page_size);
+    let preview_posts = preview_posts
+        .into_iter()
+        .skip((current_page - 1) * page_size)
+        .take(page_size)
+        .collect::<Vec<PreviewPost>>();
+
+    context!(
+        config,
+        current_page,
+        feeds => get_feed_links(config, None),
+        next_url => (current_page < total_pages).then(|| get_page_url(current_page + 1)),
+        posts => preview_posts,
+        prev_url => (current_page > 1).then(|| get_page_url(current_page - 1)),
+        public => "/public/",
+        title => "Home",
+        total_pages,
+    )
+}
+
+/// Gets the number of pages needed to list the posts.
+/// Note: there is always at least one page, even if empty.
+pub(crate) fn get_total_pages(post_count: usize, page_size: usize) -> usize {
+    ((post_count + page_size - 1) / page_size).max(1)
+}
+
+/// Gets the URL of a page of the root listing.
+pub(crate) fn get_page_url(page: usize) -> String {
+    if page == 1 {
+        "/".to_owned()
+    } else {
+        format!("/page/{page}")
+    }
+}
+
+/// Gets every tag with its number of posts, sorted by name.
+fn get_tags(posts: &HashMap<String, Post>) -> BTreeMap<String, usize> {
+    let mut tags = BTreeMap::new();
+
+    for post in posts.values() {
+        for tag in &post.front_matter.tags {
+            *tags.entry(tag.clone()).or_insert(0) += 1;
+        }
+    }
+
+    tags
+}
+
+fn get_preview_posts(
+    mut posts: Vec<(&String, &Post)>,
+    direction: SortDirection,
+) -> Vec<PreviewPost> {
+    sort_posts(&mut posts, direction);
+
+    posts
+        .into_iter()
+        .map(|(original_name, post)| PreviewPost {
+            date: to_rfc2822(post.published),
+            date_source: post.date_source,
+            encoded_name: post.encoded_name.clone(),
+            excerpt: post.excerpt.clone(),
+            front_matter: post.front_matter.clone(),
+            original_name: original_name.clone(),
+            published: to_rfc3339(post.published),
+            tags: post
+                .front_matter
+                .tags
+                .iter()
+                .map(|tag| TagLink {
+                    encoded_name: encode_path_segment(tag),
+                    name: tag.clone(),
+                })
+                .collect(),
+            title: post.title.clone(),
+            updated: to_rfc3339(post.updated),
+        })
+        .collect()
+}
+
+/// Sorts posts by publication date in the given direction.
+/// Posts published at the same time are sorted by slug to keep the order
+/// stable.
+pub(crate) fn sort_posts(posts: &mut [(&String, &Post)], direction: SortDirection) {
+    posts.sort_by(|(a_name, a_post), (b_name, b_post)| {
+        let by_date = a_post.published.cmp(&b_post.published);
+
+        match direction {
+            SortDirection::Ascending => by_date,
+            SortDirection::Descending => by_date.reverse(),
+        }
+        .then_with(|| a_name.cmp(b_name))
+    });
+}
+
 pub(crate) async fn get_rendered_template(
-    sender: &mpsc::Sender<(TemplateKind, oneshot::Sender<String>)>,
+    sender: &mpsc::Sender<RenderRequest>,
     kind: TemplateKind,
 ) -> Result<String> {
     let (tx, rx) = oneshot::channel();
 
     sender.send((kind, tx)).await?;
 
-    let rendered_template = rx.await?;
+    let rendered_template = rx.await.context("the render was dropped")??;
 
     Ok(rendered_template)
 }
+
+/// Requests every render upfront so that the workers run them in parallel.
+/// Returns the rendered templates in the order of the requests.
+pub(crate) async fn get_rendered_templates(
+    sender: &mpsc::Sender<RenderRequest>,
+    kinds: impl Iterator<Item = TemplateKind>,
+) -> Result<Vec<String>> {
+    let mut responses = Vec::new();
+
+    for kind in kinds {
+        let (tx, rx) = oneshot::channel();
+
+        sender.send((kind, tx)).await?;
+        responses.push(rx);
+    }
+
+    let mut rendered_templates = Vec::with_capacity(responses.len());
+
+    for response in responses {
+        rendered_templates.push(response.await.context("the render was dropped")??);
+    }
+
+    Ok(rendered_templates)
+}
+
+/// Reads the contents of a post along with its modification time.
+/// Note: invalid UTF-8 is replaced rather than rejected, as in an editor.
+pub(crate) async fn read_post_file(path: &Path) -> Result<(String, Option<SystemTime>)> {
+    let contents = read(path).await?;
+    let file_time = metadata(path).await?.modified().ok();
+
+    Ok((String::from_utf8_lossy(&contents).into_owned(), file_time))
+}
+
+/// Parses and renders the contents of a post.
+/// The publication date is resolved from the configured date sources.
+pub(crate) async fn load_post(
+    sender: &mpsc::Sender<RenderRequest>,
+    date_sources: &DateSources,
+    contents: &str,
+    encoded_name: String,
+    original_name: &str,
+    file_time: Option<SystemTime>,
+    level: CompressionLevel,
+) -> Result<Post> {
+    let (front_matter, contents) = parse_front_matter(contents)?;
+
+    let date_prefix = parse_date_prefix(original_name);
+    let candidates = DateCandidates {
+        file_name: date_prefix.map(|(date, _)| date),
+        filesystem: file_time.map(OffsetDateTime::from),
+        front_matter: front_matter.date,
+    };
+
+    let (published, date_source) =
+        if let Some((published, date_source)) = candidates.resolve(date_sources) {
+            (published, Some(date_source))
+        } else {
+            tracing::warn!("no date found for {original_name:?}, using the current time");
+
+            (OffsetDateTime::now_utc(), None)
+        };
+    let updated = front_matter.updated.unwrap_or(published);
+    let excerpt = get_excerpt(front_matter.description.as_deref(), contents);
+    // Note: a date prefix is not part of the fallback title.
+    let title = front_matter.title.clone().unwrap_or_else(|| {
+        date_prefix
+            .map(|(_, rest)| rest)
+            .filter(|rest| !rest.is_empty())
+            .unwrap_or(original_name)
+            .to_owned()
+    });
+
+    // Note: the about page is a standalone page rather than a post.
+    let template = front_matter.template.clone().unwrap_or_else(|| {
+        if original_name == "about" {
+            PAGE_TEMPLATE
+        } else {
+            POST_TEMPLATE
+        }
+        .to_owned()
+    });
+
+    let contents =
+        get_rendered_template(sender, TemplateKind::Markdown(contents.to_owned())).await?;
+    let rendered_template = get_rendered_template(
+        sender,
+        TemplateKind::Post(Box::new(PostTemplate {
+            contents: contents.clone(),
+            front_matter: front_matter.clone(),
+            template,
+            title: title.clone(),
+        })),
+    )
+    .await?;
+
+    tracing::debug!("{original_name:?} published on {published} ({date_source:?})");
+
+    Ok(Post {
+        contents,
+        date_source,
+        encoded_name,
+        excerpt,
+        front_matter,
+        published,
+        rendered_template: Page::create(rendered_template, Some(updated), level).await?,
+        title,
+        updated,
+    })
+}
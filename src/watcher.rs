@@ -1,124 +1,571 @@
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
+use anyhow::Result;
+use arc_swap::ArcSwap;
 use notify::{
-    event::{CreateKind, RemoveKind},
-    Config, Event, EventKind, RecommendedWatcher, Watcher,
+    event::{MetadataKind, ModifyKind},
+    Event, EventKind, PollWatcher, RecommendedWatcher, Watcher,
 };
 use tokio::{
-    fs::File,
-    io::AsyncReadExt,
     runtime::Handle,
-    sync::{mpsc, oneshot, Mutex},
+    sync::{mpsc, watch, Mutex},
+    time::{sleep_until, Instant},
 };
 
 use crate::{
-    file::{get_file_descriptor_from_paths, FileDescriptor},
-    state::{MaybeSystemTime, Post},
-    templates::{get_rendered_template, PostTemplate, TemplateKind},
+    cache::CompressionLevel,
+    config::{Config, WatchMode},
+    file::{get_file_descriptor_from_paths, is_post_file, FileDescriptor},
+    state::{AppState, Page, Post, Site},
+    templates::{
+        generate_initial_templates, load_post, read_post_file, render_derived_pages,
+        InitialTemplates, RenderRequest, Renderer,
+    },
 };
 
-fn async_watcher() -> notify::Result<(RecommendedWatcher, mpsc::Receiver<notify::Result<Event>>)> {
-    let (tx, rx) = mpsc::channel(1);
+/// Filesystems on which native events are missing or unreliable, as named in
+/// the mount table.
+const POLLED_FILESYSTEMS: &[&str] = &[
+    "9p",
+    "afs",
+    "ceph",
+    "cifs",
+    "fakeowner",
+    "fuse.grpcfuse",
+    "fuse.osxfs",
+    "fuse.sshfs",
+    "glusterfs",
+    "nfs",
+    "nfs4",
+    "smb3",
+    "smbfs",
+    "vboxsf",
+    "virtiofs",
+];
 
+/// Receives the events of the watchers.
+type EventReceiver = mpsc::Receiver<notify::Result<Event>>;
+
+/// Creates a watcher sending its events to the given channel.
+fn async_watcher<W>(
+    config: notify::Config,
+    tx: mpsc::Sender<notify::Result<Event>>,
+) -> notify::Result<W>
+where
+    W: Watcher,
+{
     let handle = Handle::current();
 
-    let watcher = RecommendedWatcher::new(
+    W::new(
         move |res| {
             let sender = tx.clone();
 
+            // Note: the events are dropped once the receiver is, i.e. when
+            // the watch stops.
             handle.block_on(async {
-                sender.send(res).await.unwrap();
+                let _ = sender.send(res).await;
             });
         },
-        Config::default(),
-    )?;
+        config,
+    )
+}
+
+/// Resolves the automatic mode from the filesystem of the path.
+/// Returns the mode to use and the reason why.
+fn resolve_watch_mode(mode: WatchMode, path: &Path) -> (WatchMode, String) {
+    if mode != WatchMode::Auto {
+        return (mode, "set in the configuration".to_owned());
+    }
 
-    Ok((watcher, rx))
+    match get_filesystem_type(path) {
+        Some(fs_type) if POLLED_FILESYSTEMS.contains(&fs_type.as_str()) => {
+            (WatchMode::Poll, format!("{fs_type} filesystem"))
+        }
+        Some(fs_type) => (WatchMode::Native, format!("{fs_type} filesystem")),
+        None => (WatchMode::Native, "unknown filesystem".to_owned()),
+    }
 }
 
-pub(crate) async fn async_watch<P>(
-    path: P,
-    posts: Arc<Mutex<HashMap<String, Post>>>,
-    root_template: Arc<Mutex<String>>,
-    sender: mpsc::Sender<(TemplateKind, oneshot::Sender<String>)>,
-) -> notify::Result<()>
-where
-    P: AsRef<Path>,
-{
-    let (mut watcher, mut rx) = async_watcher()?;
-
-    watcher.watch(path.as_ref(), notify::RecursiveMode::NonRecursive)?;
-
-    // https://github.com/notify-rs/notify/wiki/The-Event-Guide
-    while let Some(res) = rx.recv().await {
-        match res {
-            Ok(event) => match event.kind {
-                EventKind::Create(CreateKind::File) => {
-                    if let Some(FileDescriptor {
-                        encoded_name,
-                        original_name,
-                        path_buf,
-                    }) = get_file_descriptor_from_paths(&event.paths)
-                    {
-                        let mut file = File::open(path_buf).await?;
-                        let mut contents = vec![];
-
-                        file.read_to_end(&mut contents).await?;
-
-                        let mut posts = posts.lock().await;
-
-                        let metadata = file.metadata().await?;
-                        let created = MaybeSystemTime::new(metadata.created().ok());
-
-                        let contents = String::from_utf8_lossy(&contents).into_owned();
-
-                        let (tx, rx) = oneshot::channel();
-                        sender
-                            .send((
-                                TemplateKind::Post(PostTemplate {
-                                    contents,
-                                    title: original_name.clone(),
-                                }),
-                                tx,
-                            ))
-                            .await
-                            .unwrap();
-                        let rendered_template = rx.await.unwrap();
-
-                        let original_name_clone = original_name.clone();
-
-                        posts.insert(
-                            original_name,
-                            Post::new(created, encoded_name, rendered_template),
-                        );
-
-                        // Unlock the mutex to avoid a deadlock.
-                        drop(posts);
-
-                        match get_rendered_template(&sender, TemplateKind::Root).await {
-                            Ok(template) => {
-                                *root_template.lock().await = template;
-                            }
-                            Err(error) => eprintln!("watch error: {error:?}"),
-                        }
+/// Gets the type of the filesystem the path lives on from the mount table.
+/// Note: the mount table is only available on Linux.
+fn get_filesystem_type(path: &Path) -> Option<String> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let mount_table = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
 
-                        dbg!("Create", original_name_clone);
-                    }
+    // See proc(5): the mount point is the fifth field and the filesystem type
+    // the first one after the separator.
+    // Note: the last of the longest matching mount points is the visible one.
+    mount_table
+        .lines()
+        .filter_map(|line| {
+            let (fields, rest) = line.split_once(" - ")?;
+            let mount_point = PathBuf::from(unescape_mount_point(fields.split(' ').nth(4)?));
+            let fs_type = rest.split(' ').next()?;
+
+            path.starts_with(&mount_point)
+                .then_some((mount_point, fs_type))
+        })
+        .max_by_key(|(mount_point, _)| mount_point.as_os_str().len())
+        .map(|(_, fs_type)| fs_type.to_owned())
+}
+
+/// Decodes the octal escapes of the mount table, e.g. `\040` for a space.
+fn unescape_mount_point(mount_point: &str) -> String {
+    mount_point
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+/// Reads and renders the post at the given path, then publishes it in place
+/// of any previous version.
+/// Note: the about page is only set in `about_template`, `None` meaning that
+/// it didn't change.
+async fn publish_post(
+    config: &Config,
+    posts: &Mutex<HashMap<String, Post>>,
+    site: &ArcSwap<Site>,
+    about_template: &mut Option<Page>,
+    sender: &mpsc::Sender<RenderRequest>,
+    path: &Path,
+) -> Result<()> {
+    let Some(FileDescriptor {
+        encoded_name,
+        original_name,
+        path_buf,
+    }) = get_file_descriptor_from_paths(&[path])
+    else {
+        return Ok(());
+    };
+
+    let (contents, file_time) = read_post_file(&path_buf).await?;
+
+    let mut post = load_post(
+        sender,
+        &config.dates.sources,
+        &contents,
+        encoded_name,
+        &original_name,
+        file_time,
+        CompressionLevel::Fast,
+    )
+    .await?;
+
+    post.rendered_template = post
+        .rendered_template
+        .changed_since(site.load().get_post_page(&original_name));
+
+    tracing::debug!("publishing {original_name:?}");
+
+    if original_name == "about" {
+        *about_template = Some(post.rendered_template);
+    } else {
+        posts.lock().await.insert(original_name, post);
+    }
+
+    Ok(())
+}
+
+/// Removes the post previously published from the given path, if any.
+/// Returns whether a post was removed.
+/// Note: the about page falls back to the 404 page once cleared.
+async fn unpublish_post(
+    posts: &Mutex<HashMap<String, Post>>,
+    site: &ArcSwap<Site>,
+    about_template: &mut Option<Page>,
+    path: &Path,
+) -> bool {
+    let Some(FileDescriptor { original_name, .. }) = get_file_descriptor_from_paths(&[path]) else {
+        return false;
+    };
+
+    let was_published = if original_name == "about" {
+        about_template.replace(Page::default()).map_or_else(
+            || !site.load().about.body.is_empty(),
+            |about| !about.body.is_empty(),
+        )
+    } else {
+        posts.lock().await.remove(&original_name).is_some()
+    };
+
+    if was_published {
+        tracing::debug!("unpublishing {original_name:?}");
+    }
+
+    was_published
+}
+
+/// Renders the derived pages again, then swaps a new snapshot of the site in.
+/// Note: the current derived pages are kept if they fail to render, the
+/// posts are published anyway.
+async fn publish_site(
+    config: &Config,
+    posts: &Mutex<HashMap<String, Post>>,
+    site: &ArcSwap<Site>,
+    about_template: Option<Page>,
+    sender: &mpsc::Sender<RenderRequest>,
+) {
+    let previous = site.load_full();
+    let has_about = !about_template
+        .as_ref()
+        .unwrap_or(&previous.about)
+        .body
+        .is_empty();
+    let derived_pages = match render_derived_pages(
+        config,
+        posts,
+        sender,
+        Some(&previous.derived_pages),
+        has_about,
+        CompressionLevel::Fast,
+    )
+    .await
+    {
+        Ok(pages) => Some(pages),
+        Err(error) => {
+            tracing::error!(
+                "failed to render the derived pages, keeping the current ones: {error:#}"
+            );
+
+            None
+        }
+    };
+    let posts = posts.lock().await;
+
+    // Note: the current snapshot is read when swapping, so that the pages
+    // this change set didn't render are never reverted.
+    site.rcu(|current_site| {
+        Site::new(
+            about_template
+                .clone()
+                .unwrap_or_else(|| current_site.about.clone()),
+            derived_pages
+                .clone()
+                .unwrap_or_else(|| current_site.derived_pages.clone()),
+            current_site.error_pages.clone(),
+            &posts,
+        )
+    });
+}
+
+/// Applies a set of changed paths, then publishes the site once.
+/// Note: each path is published or unpublished depending on whether it
+/// still exists, so the kind of the events doesn't matter.
+async fn apply_changes(
+    config: &Config,
+    posts: &Mutex<HashMap<String, Post>>,
+    site: &ArcSwap<Site>,
+    sender: &mpsc::Sender<RenderRequest>,
+    changes: HashMap<PathBuf, usize>,
+) {
+    let started = Instant::now();
+    let events = changes.values().sum::<usize>();
+    let (mut published, mut unpublished, mut failed) = (0, 0, 0);
+    let mut about_template = None;
+
+    for path in changes.keys() {
+        if path.is_file() {
+            match publish_post(config, posts, site, &mut about_template, sender, path).await {
+                Ok(()) => published += 1,
+                Err(error) => {
+                    failed += 1;
+
+                    tracing::warn!("failed to publish {}: {error:#}", path.display());
                 }
-                EventKind::Remove(RemoveKind::File) => {
-                    match get_rendered_template(&sender, TemplateKind::Root).await {
-                        Ok(template) => {
-                            *root_template.lock().await = template;
+            }
+        } else if unpublish_post(posts, site, &mut about_template, path).await {
+            unpublished += 1;
+        }
+    }
+
+    if published + unpublished > 0 {
+        publish_site(config, posts, site, about_template, sender).await;
+    }
+
+    tracing::info!(
+        events,
+        paths = changes.len(),
+        published,
+        unpublished,
+        failed,
+        elapsed = ?started.elapsed(),
+        "applied a change set"
+    );
+}
+
+/// Checks whether an event may change a post.
+/// Note: polling without comparing the contents only reports a new
+/// modification time.
+fn is_relevant(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Any
+            | EventKind::Create(_)
+            | EventKind::Modify(
+                ModifyKind::Any
+                    | ModifyKind::Data(_)
+                    | ModifyKind::Metadata(MetadataKind::WriteTime)
+                    | ModifyKind::Name(_)
+                    | ModifyKind::Other
+            )
+            | EventKind::Remove(_)
+    )
+}
+
+/// Creates the watchers of the given paths, polling only the ones requiring
+/// it, along with the receiver of their events.
+/// Note: comparing the contents catches the changes a coarse modification
+/// time would miss, but reads every polled file at each scan.
+fn create_watchers(
+    config: &Config,
+    paths: &[&Path],
+    compare_contents: bool,
+) -> notify::Result<(Vec<Box<dyn Watcher + Send>>, EventReceiver)> {
+    let (tx, rx) = mpsc::channel(1);
+    let mut native_paths = Vec::new();
+    let mut polled_paths = Vec::new();
+
+    for path in paths {
+        let (mode, reason) = resolve_watch_mode(config.watcher.mode, path);
+
+        tracing::info!(path = %path.display(), ?mode, reason, "watching");
+
+        if mode == WatchMode::Poll {
+            polled_paths.push(*path);
+        } else {
+            native_paths.push(*path);
+        }
+    }
+
+    let mut watchers = Vec::<Box<dyn Watcher + Send>>::new();
+
+    if !native_paths.is_empty() {
+        let mut watcher =
+            async_watcher::<RecommendedWatcher>(notify::Config::default(), tx.clone())?;
+
+        for path in native_paths {
+            watcher.watch(path, notify::RecursiveMode::NonRecursive)?;
+        }
+
+        watchers.push(Box::new(watcher));
+    }
+
+    if !polled_paths.is_empty() {
+        let mut watcher = async_watcher::<PollWatcher>(
+            notify::Config::default()
+                .with_poll_interval(Duration::from_millis(config.watcher.poll_interval_ms))
+                .with_compare_contents(compare_contents),
+            tx,
+        )?;
+
+        for path in polled_paths {
+            watcher.watch(path, notify::RecursiveMode::NonRecursive)?;
+        }
+
+        watchers.push(Box::new(watcher));
+    }
+
+    Ok((watchers, rx))
+}
+
+/// Debounces the events of a watcher per path.
+struct Debouncer {
+    debounce: Duration,
+    filter: fn(&Path) -> bool,
+    /// Number of events and deadline of the changed paths not applied yet.
+    pending: HashMap<PathBuf, (usize, Instant)>,
+    rx: EventReceiver,
+}
+
+impl Debouncer {
+    fn new(rx: EventReceiver, debounce: Duration, filter: fn(&Path) -> bool) -> Self {
+        Self {
+            debounce,
+            filter,
+            pending: HashMap::new(),
+            rx,
+        }
+    }
+
+    /// Waits for the next change set, i.e. the paths matching the filter
+    /// which haven't changed for the debounce window since their last event.
+    /// Returns the number of events per path, or `None` once the watcher stops.
+    /// Note: a path changing steadily never holds back the other ones.
+    async fn next_change_set(&mut self) -> Option<HashMap<PathBuf, usize>> {
+        // https://github.com/notify-rs/notify/wiki/The-Event-Guide
+        loop {
+            let next_deadline = self.pending.values().map(|(_, deadline)| *deadline).min();
+
+            tokio::select! {
+                res = self.rx.recv() => match res? {
+                    Ok(event) if is_relevant(&event.kind) => {
+                        for path in event.paths {
+                            if (self.filter)(&path) {
+                                let (events, deadline) =
+                                    self.pending.entry(path).or_insert((0, Instant::now()));
+
+                                *events += 1;
+                                *deadline = Instant::now() + self.debounce;
+                            }
                         }
-                        Err(error) => eprintln!("watch error: {error:?}"),
                     }
+                    Ok(_) => (),
+                    Err(error) => tracing::warn!("watch error: {error}"),
+                },
+                () = sleep_until(next_deadline.unwrap_or_else(Instant::now)),
+                    if next_deadline.is_some() =>
+                {
+                    let now = Instant::now();
+                    let (changes, pending) = std::mem::take(&mut self.pending)
+                        .into_iter()
+                        .partition::<HashMap<_, _>, _>(|(_, (_, deadline))| *deadline <= now);
+
+                    self.pending = pending;
 
-                    dbg!("Delete");
+                    return Some(
+                        changes
+                            .into_iter()
+                            .map(|(path, (events, _))| (path, events))
+                            .collect(),
+                    );
                 }
-                _ => (),
-            },
-            Err(error) => eprintln!("watch error: {error:?}"),
+            }
+        }
+    }
+}
+
+/// Watches the posts and publishes the changes.
+/// Events are debounced per path: a path is applied once it hasn't changed
+/// for the configured window, which coalesces the bursts of events editors
+/// produce when saving.
+pub(crate) async fn async_watch(
+    state: AppState,
+    sender: mpsc::Sender<RenderRequest>,
+) -> notify::Result<()> {
+    let (_watchers, rx) = create_watchers(&state.config, &[&state.config.paths.posts], true)?;
+    // Note: swap and temporary files are ignored.
+    let mut debouncer = Debouncer::new(
+        rx,
+        Duration::from_millis(state.config.watcher.debounce_ms),
+        is_post_file,
+    );
+
+    while let Some(changes) = debouncer.next_change_set().await {
+        let _publishing = state.publishing.lock().await;
+
+        apply_changes(&state.config, &state.posts, &state.site, &sender, changes).await;
+    }
+
+    Ok(())
+}
+
+/// Checks that the path is not a hidden or a backup file.
+fn is_template_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map_or(false, |name| !name.starts_with('.') && !name.ends_with('~'))
+}
+
+/// Loads the templates again then renders every page with them.
+async fn reload_site(
+    state: &AppState,
+    sender: &mpsc::Sender<RenderRequest>,
+    renderer: &watch::Sender<Arc<Renderer>>,
+    changes: &HashMap<PathBuf, usize>,
+) {
+    let started = Instant::now();
+
+    // Note: the current templates and themes are kept if the new ones are
+    // invalid, or fail to render the site.
+    let previous_renderer = match Renderer::new(&state.config) {
+        Ok(new_renderer) => renderer.send_replace(Arc::new(new_renderer)),
+        Err(error) => {
+            tracing::error!("failed to load the templates, keeping the current ones: {error:#}");
+
+            return;
         }
+    };
+
+    let previous = state.site.load_full();
+
+    match generate_initial_templates(
+        &state.config,
+        Arc::clone(&state.posts),
+        sender.clone(),
+        Some(&previous),
+    )
+    .await
+    {
+        Ok(InitialTemplates {
+            about_template,
+            derived_pages,
+            error_pages,
+            failed_posts,
+        }) => {
+            state.site.store(Arc::new(Site::new(
+                about_template,
+                derived_pages,
+                error_pages,
+                &*state.posts.lock().await,
+            )));
+
+            tracing::info!(
+                events = changes.values().sum::<usize>(),
+                paths = changes.len(),
+                failed = failed_posts.len(),
+                elapsed = ?started.elapsed(),
+                "reloaded the templates and the themes"
+            );
+        }
+        Err(error) => {
+            renderer.send_replace(previous_renderer);
+
+            tracing::error!("failed to reload the site, keeping the current one: {error:#}");
+        }
+    }
+}
+
+/// Watches the templates, the public assets and the syntax highlighting
+/// themes, rendering every page again when they change.
+pub(crate) async fn async_watch_templates(
+    state: AppState,
+    sender: mpsc::Sender<RenderRequest>,
+    renderer: watch::Sender<Arc<Renderer>>,
+) -> notify::Result<()> {
+    // Note: the public directory holds the assets fingerprinted by the
+    // templates, and is the default templates directory.
+    let mut paths = [
+        &state.config.paths.public,
+        &state.config.paths.templates,
+        &state.config.paths.themes,
+    ]
+    .into_iter()
+    .filter(|path| path.is_dir())
+    .map(PathBuf::as_path)
+    .collect::<Vec<&Path>>();
+
+    paths.dedup();
+
+    // Note: the public directory holds many assets, e.g. fonts, which are not
+    // worth reading at each scan.
+    let (_watchers, rx) = create_watchers(&state.config, &paths, false)?;
+    let mut debouncer = Debouncer::new(
+        rx,
+        Duration::from_millis(state.config.watcher.debounce_ms),
+        is_template_file,
+    );
+
+    while let Some(changes) = debouncer.next_change_set().await {
+        let _publishing = state.publishing.lock().await;
+
+        reload_site(&state, &sender, &renderer, &changes).await;
     }
 
     Ok(())